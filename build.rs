@@ -1,10 +1,151 @@
+use std::{env, fmt::Write as _, fs, path::Path};
+
 fn main() {
+    // first, the linker runs this script again without the cargo
+    // environment to explain undefined symbols
     linker_be_nice();
+    convert_icons();
+
+    // the lib also builds for the host, for its unit tests
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("xtensa") {
+        return;
+    }
+    println!(
+        "cargo:rustc-link-arg=-Wl,--error-handling-script={}",
+        std::env::current_exe().unwrap().display()
+    );
     println!("cargo:rustc-link-arg=-Tdefmt.x");
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
 }
 
+/// Turn every PBM and PNG in `icons/` into an `ImageRaw` constant named
+/// after the file, written to `$OUT_DIR/icons.rs`.
+fn convert_icons() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=icons");
+
+    let mut paths: Vec<_> = fs::read_dir("icons")
+        .expect("icons directory is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pbm" || ext == "png"))
+        .collect();
+    paths.sort();
+
+    let mut out = String::new();
+    let mut names = Vec::new();
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let bytes = fs::read(&path).unwrap();
+        let (width, data) = if path.extension().is_some_and(|ext| ext == "png") {
+            parse_png(&bytes)
+        } else {
+            parse_pbm(&bytes).map_err(String::from)
+        }
+        .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+
+        let name = path.file_stem().unwrap().to_str().unwrap().to_uppercase();
+        if names.contains(&name) {
+            panic!("{}: another icon has the same name", path.display());
+        }
+        names.push(name.clone());
+        writeln!(
+            out,
+            "pub const {}: ImageRaw<'static, BinaryColor> = ImageRaw::new(&{:?}, {});",
+            name, data, width
+        )
+        .unwrap();
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("icons.rs"), out).unwrap();
+}
+
+/// Parse a plain (P1) or raw (P4) PBM. Returns the width and the pixels
+/// packed MSB first with every row padded to a whole byte, which is the
+/// layout of both P4 and `ImageRaw<BinaryColor>`.
+fn parse_pbm(bytes: &[u8]) -> Result<(u32, Vec<u8>), &'static str> {
+    let mut pos = 0;
+
+    // next whitespace separated header token, skipping comments
+    let token = |pos: &mut usize| -> Option<String> {
+        loop {
+            while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            if bytes.get(*pos) != Some(&b'#') {
+                break;
+            }
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        }
+        let start = *pos;
+        while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        (start < *pos).then(|| String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+    };
+
+    let magic = token(&mut pos).ok_or("empty file")?;
+    let width: usize = token(&mut pos).and_then(|t| t.parse().ok()).ok_or("bad width")?;
+    let height: usize = token(&mut pos).and_then(|t| t.parse().ok()).ok_or("bad height")?;
+    let stride = width.div_ceil(8);
+
+    match magic.as_str() {
+        "P4" => {
+            // a single whitespace byte separates the header from the data
+            let data = bytes.get(pos + 1..pos + 1 + stride * height).ok_or("truncated data")?;
+            Ok((width as u32, data.to_vec()))
+        }
+        "P1" => {
+            let mut data = vec![0u8; stride * height];
+            let mut pixels = bytes[pos..].iter().filter(|b| **b == b'0' || **b == b'1');
+            for y in 0..height {
+                for x in 0..width {
+                    if *pixels.next().ok_or("truncated data")? == b'1' {
+                        data[y * stride + x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+            Ok((width as u32, data))
+        }
+        _ => Err("not a P1 or P4 PBM"),
+    }
+}
+
+/// Decode a PNG of any color type. Dark opaque pixels are on, like the
+/// black pixels of a PBM. Returns the width and the pixels packed like
+/// `parse_pbm` does.
+fn parse_png(bytes: &[u8]) -> Result<(u32, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buf = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|err| err.to_string())?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let stride = width.div_ceil(8);
+    let mut data = vec![0u8; stride * height];
+
+    for (i, pixel) in buf[..info.buffer_size()].chunks(channels).enumerate() {
+        let (luma, alpha) = match *pixel {
+            [y] => (y as u32, 255),
+            [y, a] => (y as u32, a),
+            [r, g, b] => ((r as u32 * 3 + g as u32 * 6 + b as u32) / 10, 255),
+            [r, g, b, a] => ((r as u32 * 3 + g as u32 * 6 + b as u32) / 10, a),
+            _ => return Err(String::from("unexpected color type")),
+        };
+        if luma < 128 && alpha >= 128 {
+            let (x, y) = (i % width, i / width);
+            data[y * stride + x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    Ok((width as u32, data))
+}
+
 fn linker_be_nice() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() > 1 {
@@ -63,9 +204,4 @@ fn linker_be_nice() {
 
         std::process::exit(0);
     }
-
-    println!(
-        "cargo:rustc-link-arg=-Wl,--error-handling-script={}",
-        std::env::current_exe().unwrap().display()
-    );
 }
@@ -0,0 +1,253 @@
+use esp_hal::gpio::AnyPin;
+
+/// Where the log goes. Its pins can not be used for anything else. The
+/// board feature in Cargo.toml points esp-println at the same one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Console {
+    /// UART0 on GPIO43 (TX) and GPIO44 (RX).
+    Uart0,
+    /// The built-in USB Serial/JTAG on GPIO19 (D-) and GPIO20 (D+).
+    UsbJtag,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct I2cPins {
+    pub sda: u8,
+    pub scl: u8,
+}
+
+/// OLED controller of a panel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Controller {
+    /// 128x32 or 128x64.
+    Ssd1306,
+    /// 128x64 only, common on 1.3" modules.
+    Sh1106,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayConfig {
+    pub controller: Controller,
+    /// Rows, 32 or 64.
+    pub height: u8,
+    pub pins: I2cPins,
+    /// I2C clock. The panels are specified for 400 kHz, most also run
+    /// fine at 1 MHz.
+    pub i2c_khz: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SdPins {
+    pub cs: u8,
+    pub sck: u8,
+    pub mosi: u8,
+    pub miso: u8,
+    /// Card detect switch, low with a card in.
+    pub detect: u8,
+}
+
+/// GPIO numbers of one hardware revision.
+#[derive(Copy, Clone, Debug)]
+pub struct BoardConfig {
+    pub name: &'static str,
+    pub console: Console,
+    pub top_display: DisplayConfig,
+    /// `None` when the menu shares the top panel.
+    pub bottom_display: Option<DisplayConfig>,
+    pub button_up: u8,
+    pub button_down: u8,
+    pub button_select: u8,
+    /// Select again when it is on an RTC pin, GPIO0 to GPIO21, so it can
+    /// wake the chip from deep sleep. Without one the device only light
+    /// sleeps, which any button ends.
+    pub wake_pin: Option<u8>,
+    /// WS2812 data line.
+    pub led: u8,
+    pub sd: SdPins,
+}
+
+/// ESP32-S3-DevKitC-1 on a breadboard, also the Wokwi simulation. The
+/// USB-UART bridge sits on UART0, and BOOT doubles as Select. A single
+/// 128x64 panel shows both the top bar and the menu.
+#[cfg(feature = "board-devkit")]
+pub const BOARD: BoardConfig = BoardConfig {
+    name: "devkit",
+    console: Console::Uart0,
+    top_display: DisplayConfig {
+        controller: Controller::Ssd1306,
+        height: 64,
+        pins: I2cPins { sda: 5, scl: 4 },
+        i2c_khz: 400,
+    },
+    bottom_display: None,
+    button_up: 1,
+    button_down: 2,
+    button_select: 0,
+    wake_pin: Some(0),
+    led: 38,
+    sd: SdPins { cs: 10, sck: 12, mosi: 11, miso: 13, detect: 15 },
+};
+
+/// First bitband PCB. The buttons took the UART0 pins, so the log only
+/// comes over USB. Select is not on an RTC pin and can not wake the chip
+/// from deep sleep.
+#[cfg(feature = "board-bitband-v1")]
+pub const BOARD: BoardConfig = BoardConfig {
+    name: "bitband v1",
+    console: Console::UsbJtag,
+    top_display: DisplayConfig {
+        controller: Controller::Ssd1306,
+        height: 32,
+        pins: I2cPins { sda: 5, scl: 4 },
+        i2c_khz: 400,
+    },
+    bottom_display: Some(DisplayConfig {
+        controller: Controller::Ssd1306,
+        height: 32,
+        pins: I2cPins { sda: 7, scl: 6 },
+        i2c_khz: 400,
+    }),
+    button_up: 1,
+    button_down: 43,
+    button_select: 44,
+    wake_pin: None,
+    led: 38,
+    sd: SdPins { cs: 10, sck: 12, mosi: 11, miso: 13, detect: 15 },
+};
+
+/// Second bitband PCB. The buttons moved off UART0 onto RTC pins, which
+/// frees UART0 for the log, and the menu got a taller 1.3" panel.
+#[cfg(feature = "board-bitband-v2")]
+pub const BOARD: BoardConfig = BoardConfig {
+    name: "bitband v2",
+    console: Console::Uart0,
+    top_display: DisplayConfig {
+        controller: Controller::Ssd1306,
+        height: 32,
+        pins: I2cPins { sda: 5, scl: 4 },
+        i2c_khz: 400,
+    },
+    bottom_display: Some(DisplayConfig {
+        controller: Controller::Sh1106,
+        height: 64,
+        pins: I2cPins { sda: 7, scl: 6 },
+        i2c_khz: 400,
+    }),
+    button_up: 1,
+    button_down: 2,
+    button_select: 3,
+    wake_pin: Some(3),
+    led: 38,
+    sd: SdPins { cs: 10, sck: 12, mosi: 11, miso: 13, detect: 15 },
+};
+
+#[cfg(not(any(feature = "board-devkit", feature = "board-bitband-v1", feature = "board-bitband-v2")))]
+compile_error!("select a board: feature board-devkit, board-bitband-v1 or board-bitband-v2");
+
+#[cfg(any(
+    all(feature = "board-devkit", feature = "board-bitband-v1"),
+    all(feature = "board-devkit", feature = "board-bitband-v2"),
+    all(feature = "board-bitband-v1", feature = "board-bitband-v2"),
+))]
+compile_error!("select only one board feature, e.g. with --no-default-features");
+
+const _: () = BOARD.check();
+
+impl BoardConfig {
+    /// Pins in use, `None` for parts that are not fitted.
+    const fn pins(&self) -> [Option<u8>; 13] {
+        let (bottom_sda, bottom_scl) = match self.bottom_display {
+            Some(display) => (Some(display.pins.sda), Some(display.pins.scl)),
+            None => (None, None),
+        };
+        [
+            Some(self.top_display.pins.sda),
+            Some(self.top_display.pins.scl),
+            bottom_sda,
+            bottom_scl,
+            Some(self.button_up),
+            Some(self.button_down),
+            Some(self.button_select),
+            Some(self.led),
+            Some(self.sd.cs),
+            Some(self.sd.sck),
+            Some(self.sd.mosi),
+            Some(self.sd.miso),
+            Some(self.sd.detect),
+        ]
+    }
+
+    const fn is_button(&self, pin: u8) -> bool {
+        pin == self.button_up || pin == self.button_down || pin == self.button_select
+    }
+
+    /// Fails the build if a pin is used twice, does not exist, belongs to
+    /// the flash, the octal PSRAM or the console, is a strapping pin other
+    /// than a button's, the wake pin is not Select or not an RTC pin, or
+    /// a panel has a size its controller does not come in.
+    pub const fn check(&self) {
+        self.top_display.check();
+        if let Some(display) = self.bottom_display {
+            display.check();
+        }
+
+        let pins = self.pins();
+        let mut i = 0;
+        while i < pins.len() {
+            let Some(pin) = pins[i] else {
+                i += 1;
+                continue;
+            };
+
+            match (pin, self.console) {
+                (22..=25 | 49.., _) => panic!("board: no such GPIO on the ESP32-S3"),
+                (26..=32, _) => panic!("board: GPIO26 to GPIO32 belong to the flash"),
+                (33..=37, _) => panic!("board: GPIO33 to GPIO37 belong to the octal PSRAM"),
+                // a pull at reset would change every boot. A button only
+                // does while it is held, BOOT on GPIO0 is one on purpose.
+                (0 | 3 | 45 | 46, _) if !self.is_button(pin) => panic!("board: GPIO0, GPIO3, GPIO45 and GPIO46 are strapping pins"),
+                (43 | 44, Console::Uart0) => panic!("board: GPIO43 and GPIO44 carry the UART0 console"),
+                (19 | 20, Console::UsbJtag) => panic!("board: GPIO19 and GPIO20 carry the USB console"),
+                _ => {}
+            }
+
+            let mut j = i + 1;
+            while j < pins.len() {
+                if matches!(pins[j], Some(other) if other == pin) {
+                    panic!("board: two functions share a pin");
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+
+        if let Some(pin) = self.wake_pin {
+            if pin != self.button_select {
+                panic!("board: the wake pin has to be Select");
+            }
+            if pin > 21 {
+                panic!("board: the wake pin has to be an RTC pin, GPIO0 to GPIO21");
+            }
+        }
+    }
+}
+
+impl DisplayConfig {
+    const fn check(&self) {
+        match (self.controller, self.height) {
+            (Controller::Ssd1306, 32 | 64) | (Controller::Sh1106, 64) => {}
+            _ => panic!("board: unsupported display size for the controller"),
+        }
+        if self.i2c_khz < 100 || self.i2c_khz > 1_000 {
+            panic!("board: display I2C clock outside 100 kHz to 1 MHz");
+        }
+    }
+}
+
+/// Pin `number` of the board, for main to hand out once. GPIOs are not
+/// taken from `Peripherals` by name, and `check` rules out a pin serving
+/// two functions.
+pub fn pin(number: u8) -> AnyPin<'static> {
+    // SAFETY: see above
+    unsafe { AnyPin::steal(number) }
+}
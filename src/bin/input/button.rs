@@ -1,27 +1,20 @@
-use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    channel::Channel,
-    signal::Signal,
-};
 use embassy_time::{Duration, Timer};
+use embassy_futures::select::{select3, Either3};
 use esp_hal::gpio;
 
+use crate::bus;
+use crate::watchdog::{self, Task};
+
+pub use bitband::button::ButtonEvent;
+
 const DEBOUNCE_MS: u64 = 30;
 const LONG_PRESS_MS: u64 = 600;
+/// Up and Down repeat at this rate while held.
+const REPEAT_MS: u64 = 200;
 const POLL_MS: u64 = 10;
 
-#[derive(Copy, Clone, Debug)]
-pub enum ButtonEvent {
-    Up,
-    Down,
-    Select,
-    Back,
-}
-
-
-pub static BUTTON_CH: Channel<CriticalSectionRawMutex, ButtonEvent, 4> =
-    Channel::new();
-
+/// Sleeps on the pin edges while no button is down, so it does not keep
+/// the chip out of light sleep.
 #[embassy_executor::task]
 pub async fn button_task(
     mut up: gpio::Input<'static>,
@@ -29,23 +22,34 @@ pub async fn button_task(
     mut select: gpio::Input<'static>
 ) {
     loop {
-        if up.is_low() {
-            BUTTON_CH.send(ButtonEvent::Up).await;
-            Timer::after(Duration::from_millis(200)).await;
-        }
-        if down.is_low() {
-            BUTTON_CH.send(ButtonEvent::Down).await;
-            Timer::after(Duration::from_millis(200)).await;
-        }
-        if select.is_low() {
-            Timer::after(Duration::from_millis(DEBOUNCE_MS)).await;
-            if select.is_low() {
-                handle_select_press(&mut select).await;
+        watchdog::wait(Task::Button);
+        let pressed = select3(
+            up.wait_for_falling_edge(),
+            down.wait_for_falling_edge(),
+            select.wait_for_falling_edge(),
+        )
+        .await;
+        watchdog::check_in(Task::Button);
+        Timer::after(Duration::from_millis(DEBOUNCE_MS)).await;
+
+        match pressed {
+            Either3::First(()) => repeat(&mut up, ButtonEvent::Up).await,
+            Either3::Second(()) => repeat(&mut down, ButtonEvent::Down).await,
+            Either3::Third(()) => {
+                if select.is_low() {
+                    handle_select_press(&mut select).await;
+                }
             }
         }
+    }
+}
 
-        Timer::after(Duration::from_millis(POLL_MS)).await;
-        Timer::after(Duration::from_millis(10)).await;
+/// Publishes `event` until `btn` is let go.
+async fn repeat(btn: &mut gpio::Input<'static>, event: ButtonEvent) {
+    while btn.is_low() {
+        watchdog::check_in(Task::Button);
+        bus::INPUT.publish(event);
+        Timer::after(Duration::from_millis(REPEAT_MS)).await;
     }
 }
 
@@ -54,17 +58,18 @@ async fn handle_select_press(btn: &mut gpio::Input<'static>) {
     let mut long_sent = false;
 
     while btn.is_low() {
+        watchdog::check_in(Task::Button);
         Timer::after(Duration::from_millis(POLL_MS)).await;
         elapsed += POLL_MS;
 
         if elapsed >= LONG_PRESS_MS && !long_sent {
-            BUTTON_CH.send(ButtonEvent::Back).await;
+            bus::INPUT.publish(ButtonEvent::Back);
             long_sent = true;
         }
     }
 
     // released
     if !long_sent {
-        BUTTON_CH.send(ButtonEvent::Select).await;
+        bus::INPUT.publish(ButtonEvent::Select);
     }
 }
@@ -10,7 +10,7 @@
 use core::cell::RefCell;
 
 use embassy_net::Config;
-use esp_hal::{gpio::{self, Input, InputConfig, OutputConfig, Pull}, i2c, ledc::channel, peripherals, spi};
+use esp_hal::{gpio::{self, InputConfig, OutputConfig}, i2c, ledc::channel, peripherals, spi};
 
 use bt_hci::controller::ExternalController;
 use defmt::info;
@@ -24,9 +24,7 @@ use esp_println as _;
 use esp_radio::{ble::controller::BleConnector, wifi::{ClientConfig, ModeConfig, ScanConfig, WifiController}};
 use trouble_host::prelude::*;
 
-use smart_leds::{brightness, colors, SmartLedsWrite as _};
-
-use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306, command};
+use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306, Ssd1306Async, command};
 use embedded_graphics::{
     mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
@@ -34,7 +32,7 @@ use embedded_graphics::{
     text::{Baseline, Text}
 };
 
-use embedded_sdmmc::{SdCard, TimeSource, VolumeManager};
+use embedded_sdmmc::{SdCard, VolumeManager};
 use esp_hal::spi::master::Spi;
 use esp_hal::gpio::Output;
 use esp_hal::time::Rate;
@@ -42,21 +40,43 @@ use embedded_hal_bus::spi::RefCellDevice;
 
 use alloc::{boxed::Box, string::String};
 
+mod board;
 mod input;
 mod services;
 mod ui;
 
+use board::BOARD;
 use input::button;
 
+use services::app_state;
 use services::battery;
+use services::battery_policy;
+use services::bus;
 use services::clock;
-
+use services::crash;
+use services::deauth;
+use services::health::{self, Health, Subsystem};
+use services::led;
+use services::power;
+use services::rogue_ap;
+use services::settings;
+use services::storage;
+use services::watchdog;
+
+use bitband::{frame, icons, text, widget};
+use ui::dialog;
+use ui::display;
+use ui::layout::{self, Layout};
+use ui::marquee;
 use ui::menu;
+use ui::screen;
+use ui::sh1106;
 use ui::top_bar;
 
 #[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
-    loop {}
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    crash::record(info);
+    esp_hal::system::software_reset()
 }
 
 extern crate alloc;
@@ -88,64 +108,109 @@ async fn main(spawner: Spawner) {
     esp_rtos::start(timg0.timer0);
 
     info!("Embassy initialized!");
-
-    let radio_init: &'static _ = Box::leak(Box::new(
-        esp_radio::init().expect("Failed to initialize Wi-Fi/BLE controller")
-    ));
-    let (mut _wifi_controller, _interfaces) =
-        esp_radio::wifi::new(&radio_init, peripherals.WIFI, Default::default())
-            .expect("Failed to initialize Wi-Fi controller");
-    // find more examples https://github.com/embassy-rs/trouble/tree/main/examples/esp32
-    let transport = BleConnector::new(&radio_init, peripherals.BT, Default::default()).unwrap();
-    let ble_controller = ExternalController::<_, 1>::new(transport);
-    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> =
-        HostResources::new();
-    let _stack = trouble_host::new(ble_controller, &mut resources);
+    info!("Board: {}", BOARD.name);
+
+    // every subsystem comes up on its own, a missing part leaves the
+    // rest usable
+    let radio_init = match esp_radio::init() {
+        Ok(init) => Some(&*Box::leak(Box::new(init))),
+        Err(_) => {
+            health::set(Subsystem::Wifi, Health::Error("radio init"));
+            health::set(Subsystem::Bluetooth, Health::Error("radio init"));
+            None
+        }
+    };
 
     let client_conf = ClientConfig::default()
         .with_ssid(String::new())
         .with_password(String::new());
     let modeconf = ModeConfig::Client(client_conf);
 
-    _wifi_controller.set_config(&modeconf).unwrap();
-    _wifi_controller.start().unwrap();
+    let mut wifi = None;
+    let mut ble_transport = None;
+    if let Some(radio_init) = radio_init {
+        match esp_radio::wifi::new(radio_init, peripherals.WIFI, Default::default()) {
+            Ok((mut controller, interfaces)) => {
+                match controller.set_config(&modeconf).and_then(|()| controller.start()) {
+                    Ok(()) => {
+                        health::set(Subsystem::Wifi, Health::Ok);
+                        wifi = Some((controller, interfaces));
+                    }
+                    Err(_) => health::set(Subsystem::Wifi, Health::Error("start failed")),
+                }
+            }
+            Err(_) => health::set(Subsystem::Wifi, Health::Error("driver init")),
+        }
+
+        // find more examples https://github.com/embassy-rs/trouble/tree/main/examples/esp32
+        match BleConnector::new(radio_init, peripherals.BT, Default::default()) {
+            Ok(transport) => {
+                health::set(Subsystem::Bluetooth, Health::Ok);
+                ble_transport = Some(transport);
+            }
+            Err(_) => health::set(Subsystem::Bluetooth, Health::Error("HCI init")),
+        }
+    }
+    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> =
+        HostResources::new();
+    let _stack = match ble_transport {
+        Some(transport) => Some(trouble_host::new(ExternalController::<_, 1>::new(transport), &mut resources)),
+        None => None,
+    };
 
     let mut pulse_code = smart_led_buffer!(1);
     let frequency = Rate::from_mhz(80);
-    let rmt = Rmt::new(peripherals.RMT, frequency).expect("Failed to initialize RMT0");
-    let mut led = SmartLedsAdapter::new(rmt.channel0, peripherals.GPIO38, &mut pulse_code);
-    info!("RGB light initialized!");
-
-    let disp_top_i2c = i2c::master::I2c::new(peripherals.I2C0, i2c::master::Config::default())
-        .unwrap()
-        .with_sda(peripherals.GPIO5)
-        .with_scl(peripherals.GPIO4);
-    let disp_top_interface = I2CDisplayInterface::new(disp_top_i2c);
-    let mut display_top = Ssd1306::new(
-        disp_top_interface,
-        DisplaySize128x32,
-        DisplayRotation::Rotate0
-    ).into_buffered_graphics_mode();
-    display_top.init().unwrap();
-    display_top.clear_buffer();
-    display_top.flush().unwrap();
-    info!("Top display initialized");
-
-    let disp_bot_i2c = i2c::master::I2c::new(peripherals.I2C1, i2c::master::Config::default())
-        .unwrap()
-        .with_sda(peripherals.GPIO7)
-        .with_scl(peripherals.GPIO6);
-    let disp_bot_interface = I2CDisplayInterface::new(disp_bot_i2c);
-    let mut display_bot = Ssd1306::new(
-        disp_bot_interface,
-        DisplaySize128x32,
-        DisplayRotation::Rotate0
-    ).into_buffered_graphics_mode();
-    display_bot.init().unwrap();
-    display_bot.clear_buffer();
-    display_bot.flush().unwrap();
-    info!("Bottom display initialized");
+    let led = match Rmt::new(peripherals.RMT, frequency) {
+        Ok(rmt) => {
+            health::set(Subsystem::Led, Health::Ok);
+            Some(SmartLedsAdapter::new(rmt.channel0, board::pin(BOARD.led), &mut pulse_code))
+        }
+        Err(_) => {
+            health::set(Subsystem::Led, Health::Error("RMT init"));
+            None
+        }
+    };
 
+    let i2c_config = |config: board::DisplayConfig| {
+        i2c::master::Config::default().with_frequency(Rate::from_khz(config.i2c_khz))
+    };
+
+    let display_top = match i2c::master::I2c::new(peripherals.I2C0, i2c_config(BOARD.top_display)) {
+        Ok(i2c) => {
+            let pins = BOARD.top_display.pins;
+            let i2c = i2c.with_sda(board::pin(pins.sda)).with_scl(board::pin(pins.scl)).into_async();
+            display::init(i2c, BOARD.top_display, Subsystem::TopDisplay).await
+        }
+        Err(_) => {
+            health::set(Subsystem::TopDisplay, Health::Error("I2C config"));
+            None
+        }
+    };
+
+    let display_bot = match BOARD.bottom_display {
+        None => {
+            health::set(Subsystem::BottomDisplay, Health::NotFitted);
+            None
+        }
+        Some(config) => match i2c::master::I2c::new(peripherals.I2C1, i2c_config(config)) {
+            Ok(i2c) => {
+                let i2c = i2c.with_sda(board::pin(config.pins.sda)).with_scl(board::pin(config.pins.scl)).into_async();
+                display::init(i2c, config, Subsystem::BottomDisplay).await
+            }
+            Err(_) => {
+                health::set(Subsystem::BottomDisplay, Health::Error("I2C config"));
+                None
+            }
+        },
+    };
+
+    // the UI tasks size their frames from this, set it before they start
+    let layout = Layout::for_panels(
+        display_top.as_ref().map(display::Display::height),
+        display_bot.as_ref().map(display::Display::height),
+    );
+    layout::set(layout);
+    info!("Layout: top bar {} rows, menu {} rows", layout.top_bar_height(), layout.menu_height());
 
     // let text_style = MonoTextStyleBuilder::new()
     //     .font(&FONT_6X10)
@@ -172,87 +237,86 @@ async fn main(spawner: Spawner) {
     //     Timer::after(Duration::from_secs(1)).await;
     // }
     
-    let btn_up = gpio::Input::new(peripherals.GPIO1, InputConfig::default().with_pull(gpio::Pull::Up));
-    let btn_down = gpio::Input::new(peripherals.GPIO43, InputConfig::default().with_pull(gpio::Pull::Up));
-    let btn_sel = gpio::Input::new(peripherals.GPIO44, InputConfig::default().with_pull(gpio::Pull::Up));
+    let mut btn_up = gpio::Input::new(board::pin(BOARD.button_up), InputConfig::default().with_pull(gpio::Pull::Up));
+    let mut btn_down = gpio::Input::new(board::pin(BOARD.button_down), InputConfig::default().with_pull(gpio::Pull::Up));
+    let mut btn_sel = gpio::Input::new(board::pin(BOARD.button_select), InputConfig::default().with_pull(gpio::Pull::Up));
+    // any button ends a light sleep
+    for btn in [&mut btn_up, &mut btn_down, &mut btn_sel] {
+        if btn.wakeup_enable(true, gpio::WakeEvent::LowLevel).is_err() {
+            info!("Button can not wake from light sleep");
+        }
+    }
+
+    let (wifi_ctrl, sniffer) = match wifi {
+        Some((controller, interfaces)) => {
+            let controller: &'static mut WifiController<'static> = Box::leak(Box::new(controller));
+            (Some(controller), Some(Box::leak(Box::new(interfaces.sniffer))))
+        }
+        None => (None, None),
+    };
 
-    let wifi_ctrl: &'static mut WifiController<'static> = Box::leak(Box::new(_wifi_controller));
+    // subscribe before spawning, so the UI tasks see everything the
+    // other tasks publish at boot
+    let menu_buttons = bus::INPUT.subscribe();
+    let top_bar_events = bus::TOP_BAR.subscribe();
 
     spawner.spawn(button::button_task(btn_up, btn_down, btn_sel)).unwrap();
-    spawner.spawn(ui::menu::menu_task(display_bot)).unwrap();
-    spawner.spawn(ui::top_bar::status_task(display_top)).unwrap();
+    if let Some(display_top) = display_top {
+        spawner.spawn(ui::display::display_task(display_top, layout.content(true), "Top", watchdog::Task::TopDisplay, BOARD.top_display.i2c_khz)).unwrap();
+    }
+    if let (Some(display_bot), Some(config)) = (display_bot, BOARD.bottom_display) {
+        spawner.spawn(ui::display::display_task(display_bot, layout.content(false), "Bottom", watchdog::Task::BottomDisplay, config.i2c_khz)).unwrap();
+    }
+    spawner.spawn(ui::menu::menu_task(menu_buttons)).unwrap();
+    spawner.spawn(ui::top_bar::status_task(top_bar_events)).unwrap();
+    spawner.spawn(services::bus::bus_stats_task()).unwrap();
+    spawner.spawn(ui::screen::screen_task()).unwrap();
     spawner.spawn(services::battery::battery_task()).unwrap();
+    spawner.spawn(services::battery_policy::battery_policy_task()).unwrap();
+    spawner.spawn(services::led::state_task()).unwrap();
     spawner.spawn(ui::menu::radio_task()).unwrap();
     spawner.spawn(ui::menu::ble_scan_task()).unwrap();
-    // spawner.spawn(services::clock::clock_task()).unwrap();
+    spawner.spawn(services::clock::clock_task()).unwrap();
     spawner.spawn(ui::menu::wifi_scan_task(wifi_ctrl)).unwrap();
+    spawner.spawn(services::rogue_ap::rogue_ap_task()).unwrap();
+    if let Some(sniffer) = sniffer {
+        spawner.spawn(services::deauth::deauth_task(sniffer)).unwrap();
+    }
 
-    let cd = Input::new(peripherals.GPIO15, InputConfig::default().with_pull(Pull::Up));
-    let cs = Output::new(peripherals.GPIO10, gpio::Level::High, OutputConfig::default());
-    let sck = peripherals.GPIO12;
-    let mosi = peripherals.GPIO11;
-    let miso = peripherals.GPIO13;
+    let cs = Output::new(board::pin(BOARD.sd.cs), gpio::Level::High, OutputConfig::default());
+    let sck = board::pin(BOARD.sd.sck);
+    let mosi = board::pin(BOARD.sd.mosi);
+    let miso = board::pin(BOARD.sd.miso);
 
     let spi_bus_config = spi::master::Config::default()
         .with_frequency(Rate::from_khz(400))
         .with_mode(spi::Mode::_0);
-    let spi_bus = spi::master::Spi::new(peripherals.SPI2, spi_bus_config)
-        .expect("Failed to initialize SPI bus")
-        .with_mosi(mosi)
-        .with_miso(miso)
-        .with_sck(sck);
-    let shared_spi_bus = RefCell::new(spi_bus);
-    let spi_device = RefCellDevice::new(&shared_spi_bus, cs, esp_hal::delay::Delay::new())
-        .expect("Failed to create SPI device");
-
-    let sdcard = SdCard::new(spi_device, esp_hal::delay::Delay::new());
-    let sd_size = sdcard.num_bytes();
-    if let Ok(bytes) = sd_size {
-        info!("SD card size: {} GiB", bytes / 1024 / 1024 / 1024);
-    } else {
-        info!("Failed to initialize SD card");
-    }
+    let volume_mgr = match spi::master::Spi::new(peripherals.SPI2, spi_bus_config) {
+        Ok(spi_bus) => storage::init(spi_bus.with_mosi(mosi).with_miso(miso).with_sck(sck), cs),
+        Err(_) => {
+            health::set(Subsystem::SdCard, Health::Error("SPI config"));
+            None
+        }
+    };
+    spawner.spawn(services::storage::storage_task(volume_mgr)).unwrap();
+    spawner.spawn(services::settings::settings_task()).unwrap();
+
+    let rtc = esp_hal::rtc_cntl::Rtc::new(peripherals.LPWR);
+    spawner.spawn(services::power::power_task(rtc)).unwrap();
+    spawner.spawn(services::crash::crash_report_task()).unwrap();
+
+    let timg1 = TimerGroup::new(peripherals.TIMG1);
+    spawner.spawn(services::watchdog::watchdog_task(timg1.wdt)).unwrap();
+
+    spawner.spawn(services::health::self_test_task()).unwrap();
 
-    let volume_mgr = VolumeManager::new(sdcard, DummyTime);
-    let volume0 = volume_mgr.open_volume(embedded_sdmmc::VolumeIdx(0)).expect("Failed to open volume 0");
-    let root_dir = volume0.open_root_dir().expect("Failed to open root directory");
-
-    shared_spi_bus.borrow_mut().apply_config(&spi::master::Config::default()
-        .with_frequency(Rate::from_mhz(2))
-        .with_mode(spi::Mode::_0)
-    ).expect("Failed to speed up the SD card");
-
-    let mut file = root_dir.open_file_in_dir("test.txt", embedded_sdmmc::Mode::ReadWriteCreateOrAppend)
-        .expect("Failed to create test.txt");
-    file.close().expect("Failed to close test.txt");
-
-    loop {
-        // info!("KEEPALIVE");
-        led.write(brightness([colors::RED].into_iter(), 10)).unwrap();
-        Timer::after(Duration::from_millis(300)).await;
-        led.write(brightness([colors::GREEN].into_iter(), 10)).unwrap();
-        Timer::after(Duration::from_millis(300)).await;
-        led.write(brightness([colors::BLUE].into_iter(), 10)).unwrap();
-        Timer::after(Duration::from_millis(300)).await;
+    // the LED adapter borrows `pulse_code`, so the LED service runs here
+    match led {
+        Some(led) => led::run(led).await,
+        None => core::future::pending::<()>().await,
     }
 
     // core::future::pending::<()>().await;
 
     // for inspiration have a look at the examples at https://github.com/esp-rs/esp-hal/tree/esp-hal-v~1.0/examples
 }
-
-// TEMP FOR TESTING THE SD CARD
-struct DummyTime;
-
-impl TimeSource for DummyTime {
-    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
-        embedded_sdmmc::Timestamp {
-            year_since_1970: 54,
-            zero_indexed_month: 0,
-            zero_indexed_day: 0,
-            hours: 0,
-            minutes: 0,
-            seconds: 0,
-        }
-    }
-}
@@ -0,0 +1,109 @@
+use core::cell::Cell;
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    watch::Watch,
+};
+
+use crate::battery_policy::BatteryLevel;
+use crate::menu::WifiApInfo;
+
+pub use bitband::battery_policy::Battery;
+
+/// Most tasks that may wait on changes of one field.
+const WATCHERS: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RadioState {
+    Idle,
+    Scanning,
+}
+
+/// Everything the UI and LED show about the device. Read a snapshot
+/// with `get`, or wait on the watch of a field to hear about changes.
+/// Only the fields some task waits on have a watch, the bars and menu
+/// redraw every frame and read the rest with `get`.
+#[derive(Copy, Clone)]
+pub struct AppState {
+    pub battery: Battery,
+    pub battery_level: BatteryLevel,
+    pub time_hhmm: (u8, u8),
+    pub selected_ap: Option<&'static WifiApInfo>,
+    pub bluetooth: bool,
+    pub radio: RadioState,
+}
+
+impl AppState {
+    const fn new() -> Self {
+        Self {
+            battery: Battery { percent: 100, charging: false, measured: false },
+            battery_level: BatteryLevel::Ok,
+            time_hhmm: (0, 0),
+            selected_ap: None,
+            bluetooth: false,
+            radio: RadioState::Idle,
+        }
+    }
+}
+
+static STATE: Mutex<CriticalSectionRawMutex, Cell<AppState>> =
+    Mutex::new(Cell::new(AppState::new()));
+
+pub static BATTERY: Watch<CriticalSectionRawMutex, Battery, WATCHERS> =
+    Watch::new_with(AppState::new().battery);
+pub static BATTERY_LEVEL: Watch<CriticalSectionRawMutex, BatteryLevel, WATCHERS> =
+    Watch::new_with(AppState::new().battery_level);
+pub static RADIO: Watch<CriticalSectionRawMutex, RadioState, WATCHERS> =
+    Watch::new_with(AppState::new().radio);
+
+pub fn get() -> AppState {
+    STATE.lock(|s| s.get())
+}
+
+/// Store `value` in the field picked by `field`, true if it changed.
+fn store<T: Copy + PartialEq>(value: T, field: impl FnOnce(&mut AppState) -> &mut T) -> bool {
+    STATE.lock(|s| {
+        let mut state = s.get();
+        let slot = field(&mut state);
+        let changed = *slot != value;
+        *slot = value;
+        s.set(state);
+        changed
+    })
+}
+
+/// Store `value` in the field picked by `field` and tell the watchers
+/// of `watch`, if it changed.
+fn set<T: Copy + PartialEq>(
+    watch: &Watch<CriticalSectionRawMutex, T, WATCHERS>,
+    value: T,
+    field: impl FnOnce(&mut AppState) -> &mut T,
+) {
+    if store(value, field) {
+        watch.sender().send(value);
+    }
+}
+
+pub fn set_battery(battery: Battery) {
+    set(&BATTERY, battery, |s| &mut s.battery);
+}
+
+pub fn set_battery_level(level: BatteryLevel) {
+    set(&BATTERY_LEVEL, level, |s| &mut s.battery_level);
+}
+
+pub fn set_time(hhmm: (u8, u8)) {
+    store(hhmm, |s| &mut s.time_hhmm);
+}
+
+pub fn select_ap(ap: Option<&'static WifiApInfo>) {
+    store(ap, |s| &mut s.selected_ap);
+}
+
+pub fn set_bluetooth(on: bool) {
+    store(on, |s| &mut s.bluetooth);
+}
+
+pub fn set_radio(radio: RadioState) {
+    set(&RADIO, radio, |s| &mut s.radio);
+}
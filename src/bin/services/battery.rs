@@ -1,12 +1,6 @@
-use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    channel::Channel,
-    signal::Signal,
-};
 use embassy_time::{Duration, Timer};
 use esp_hal::gpio;
-use crate::top_bar::StatusBar;
-use crate::top_bar::{TopBarMode, TOP_BAR_CH};
+use crate::app_state::{self, Battery};
 
 #[embassy_executor::task]
 pub async fn battery_task() {
@@ -14,20 +8,13 @@ pub async fn battery_task() {
 
     loop {
         percent = percent.saturating_sub(1); // fake ADC for now
-        // STATUS_SIGNAL.wait().await;
-        let mut status = StatusBar {
-            battery_percent: percent,
-            hours: 0,
-            minutes: 0,
-        };
-
-        status.battery_percent = percent;
-        TOP_BAR_CH
-            .send(TopBarMode::Normal {
-                battery_percent: percent,
-                time_hhmm: (0, 0),
-            })
-            .await;
+        app_state::set_battery(Battery {
+            percent,
+            // no charger sense line on this board yet
+            charging: false,
+            // so the battery policy does not act on it
+            measured: false,
+        });
 
         Timer::after(Duration::from_secs(30)).await;
     }
@@ -0,0 +1,82 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_time::Duration;
+use defmt::info;
+
+use alloc::format;
+
+use crate::app_state::{self, Battery};
+use crate::deauth;
+use crate::dialog;
+use crate::power;
+use crate::rogue_ap;
+use crate::settings;
+use crate::storage;
+use crate::top_bar::{self, TopBarMode};
+
+use bitband::battery_policy::{BatteryPolicy, PolicyAction};
+pub use bitband::battery_policy::BatteryLevel;
+
+const TOAST_SECS: u64 = 5;
+
+static SHEDDING: AtomicBool = AtomicBool::new(false);
+
+/// False while the battery is too low for scans.
+pub fn radios_allowed() -> bool {
+    !SHEDDING.load(Ordering::Relaxed)
+}
+
+/// Runs the policy on every battery reading.
+#[embassy_executor::task]
+pub async fn battery_policy_task() {
+    let mut battery = app_state::BATTERY.receiver().unwrap();
+    let mut policy = BatteryPolicy::new();
+
+    loop {
+        let sample = battery.changed().await;
+        let limits = settings::get().battery_thresholds();
+
+        let actions = policy.update(sample, &limits);
+        app_state::set_battery_level(policy.level());
+
+        for action in actions {
+            run(action, sample).await;
+        }
+    }
+}
+
+async fn run(action: PolicyAction, battery: Battery) {
+    match action {
+        PolicyAction::Warn(level) => {
+            let title = match level {
+                BatteryLevel::Critical => "BATTERY EMPTY",
+                _ => "BATTERY LOW",
+            };
+            let detail = format!("{}% left", battery.percent);
+            info!("Battery {}%, level {}", battery.percent, level as u8);
+            top_bar::show(TopBarMode::Alert { title, detail: detail.clone() });
+            dialog::alert("Battery", detail, Duration::from_secs(TOAST_SECS)).await;
+        }
+        PolicyAction::ShedRadios => {
+            info!("Battery low, radios off");
+            SHEDDING.store(true, Ordering::Relaxed);
+            if rogue_ap::monitor_enabled() {
+                rogue_ap::toggle_monitor();
+            }
+            if deauth::monitor_enabled() {
+                deauth::toggle_monitor();
+            }
+            app_state::set_bluetooth(false);
+        }
+        PolicyAction::RestoreRadios => {
+            info!("Battery recovered, radios allowed");
+            SHEDDING.store(false, Ordering::Relaxed);
+        }
+        PolicyAction::Shutdown => {
+            info!("Battery critical, shutting down");
+            settings::flush().await;
+            storage::unmount().await;
+            power::power_off();
+        }
+    }
+}
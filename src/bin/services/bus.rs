@@ -0,0 +1,139 @@
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    pubsub::{PubSubChannel, Subscriber, WaitResult},
+};
+use embassy_time::{Duration, Timer};
+use defmt::{info, warn};
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::button::ButtonEvent;
+use crate::top_bar::TopBarMode;
+
+/// Most tasks that may listen to one topic at the same time.
+const SUBSCRIBERS: usize = 6;
+/// Everything is published through immediate publishers, which do not
+/// take a slot.
+const PUBLISHERS: usize = 1;
+const STATS_INTERVAL_SECS: u64 = 60;
+
+pub static INPUT: Topic<ButtonEvent, 4> = Topic::new("input");
+/// What the top bar shows, for status_task.
+pub static TOP_BAR: Topic<TopBarMode, 8> = Topic::new("top bar");
+
+/// One kind of message, delivered to every subscriber. Publishing never
+/// waits: when a subscriber falls `CAP` messages behind, the oldest
+/// message is dropped for it and counted. Only for status updates that
+/// a newer one makes up for, requests that must arrive go through a
+/// `Channel` to the task that serves them.
+pub struct Topic<T: Clone, const CAP: usize> {
+    name: &'static str,
+    channel: PubSubChannel<CriticalSectionRawMutex, T, CAP, SUBSCRIBERS, PUBLISHERS>,
+    published: AtomicU32,
+    /// Messages pushed out before every subscriber had read them.
+    overwritten: AtomicU32,
+    /// Messages missed, summed over all subscribers.
+    lagged: AtomicU32,
+}
+
+impl<T: Clone + 'static, const CAP: usize> Topic<T, CAP> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            channel: PubSubChannel::new(),
+            published: AtomicU32::new(0),
+            overwritten: AtomicU32::new(0),
+            lagged: AtomicU32::new(0),
+        }
+    }
+
+    pub fn publish(&self, msg: T) {
+        if self.channel.is_full() {
+            self.overwritten.fetch_add(1, Ordering::Relaxed);
+        }
+        self.channel.immediate_publisher().publish_immediate(msg);
+        self.published.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Only messages published from now on are seen. Panics if more
+    /// than `SUBSCRIBERS` tasks listen to this topic.
+    pub fn subscribe(&'static self) -> TopicSubscriber<T, CAP> {
+        match self.channel.subscriber() {
+            Ok(sub) => TopicSubscriber { topic: self, sub },
+            Err(_) => panic!("too many subscribers on topic {}", self.name),
+        }
+    }
+
+    pub fn stats(&self) -> TopicStats {
+        TopicStats {
+            name: self.name,
+            published: self.published.load(Ordering::Relaxed),
+            overwritten: self.overwritten.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct TopicSubscriber<T: Clone + 'static, const CAP: usize> {
+    topic: &'static Topic<T, CAP>,
+    sub: Subscriber<'static, CriticalSectionRawMutex, T, CAP, SUBSCRIBERS, PUBLISHERS>,
+}
+
+impl<T: Clone + 'static, const CAP: usize> TopicSubscriber<T, CAP> {
+    pub async fn next(&mut self) -> T {
+        loop {
+            match self.sub.next_message().await {
+                WaitResult::Message(msg) => return msg,
+                WaitResult::Lagged(missed) => self.missed(missed),
+            }
+        }
+    }
+
+    /// The next message if one is waiting.
+    pub fn try_next(&mut self) -> Option<T> {
+        loop {
+            match self.sub.try_next_message()? {
+                WaitResult::Message(msg) => return Some(msg),
+                WaitResult::Lagged(missed) => self.missed(missed),
+            }
+        }
+    }
+
+    fn missed(&self, count: u64) {
+        self.topic.lagged.fetch_add(count as u32, Ordering::Relaxed);
+        warn!("bus: subscriber of {} missed {} messages", self.topic.name, count);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TopicStats {
+    pub name: &'static str,
+    pub published: u32,
+    pub overwritten: u32,
+    pub lagged: u32,
+}
+
+pub fn stats() -> [TopicStats; 2] {
+    [INPUT.stats(), TOP_BAR.stats()]
+}
+
+/// Logs the counters of every topic that lost messages since the last
+/// report.
+#[embassy_executor::task]
+pub async fn bus_stats_task() {
+    let mut reported = [0u32; 2];
+
+    loop {
+        Timer::after(Duration::from_secs(STATS_INTERVAL_SECS)).await;
+
+        for (stats, reported) in stats().iter().zip(reported.iter_mut()) {
+            if stats.lagged != *reported {
+                info!(
+                    "bus: {} published {}, overwritten {}, missed {}",
+                    stats.name, stats.published, stats.overwritten, stats.lagged
+                );
+                *reported = stats.lagged;
+            }
+        }
+    }
+}
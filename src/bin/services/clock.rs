@@ -0,0 +1,44 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::app_state;
+use crate::settings;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// No RTC battery, time of day is uptime plus a user set offset
+static OFFSET_SECS: AtomicU32 = AtomicU32::new(0);
+
+pub fn now_secs_of_day() -> u32 {
+    ((Instant::now().as_secs() + OFFSET_SECS.load(Ordering::Relaxed) as u64) % SECS_PER_DAY) as u32
+}
+
+pub fn now_hhmm() -> (u8, u8) {
+    let secs = now_secs_of_day();
+    ((secs / 3600) as u8, ((secs / 60) % 60) as u8)
+}
+
+/// Move the clock by `delta_secs`, wrapping around midnight.
+pub fn adjust(delta_secs: i32) {
+    let day = SECS_PER_DAY as i64;
+    let offset = OFFSET_SECS.load(Ordering::Relaxed) as i64;
+    let offset = (offset + delta_secs as i64).rem_euclid(day);
+    OFFSET_SECS.store(offset as u32, Ordering::Relaxed);
+    app_state::set_time(now_hhmm());
+    settings::refresh_brightness();
+}
+
+#[embassy_executor::task]
+pub async fn clock_task() {
+    app_state::set_time(now_hhmm());
+
+    loop {
+        // wake at the start of every minute to follow the night schedule
+        let into_minute = now_secs_of_day() % 60;
+        Timer::after(Duration::from_secs((60 - into_minute) as u64)).await;
+
+        app_state::set_time(now_hhmm());
+        settings::refresh_brightness();
+    }
+}
@@ -0,0 +1,261 @@
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use defmt::{error, info};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::dialog;
+use crate::health;
+use crate::menu::MenuCommand;
+use crate::storage;
+use crate::top_bar::{self, TopBarMode};
+
+pub const LOG_FILE: &str = "CRASH.LOG";
+
+const RECORD_MAGIC: u32 = 0xC4A5_11ED;
+const MESSAGE_LEN: usize = 96;
+const LOCATION_LEN: usize = 48;
+const FRAMES: usize = 8;
+/// Stack words above the panic handler searched for return addresses.
+const STACK_SCAN_WORDS: usize = 512;
+
+/// Where code can run from on the ESP32-S3.
+const IRAM: core::ops::Range<u32> = 0x4037_0000..0x403E_0000;
+const IROM: core::ops::Range<u32> = 0x4200_0000..0x4400_0000;
+/// End of internal data RAM, the stack scan stops there.
+const DRAM_END: usize = 0x3FD0_0000;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Record {
+    magic: u32,
+    message_len: u32,
+    location_len: u32,
+    frame_count: u32,
+    uptime_ms: u64,
+    message: [u8; MESSAGE_LEN],
+    location: [u8; LOCATION_LEN],
+    frames: [u32; FRAMES],
+}
+
+// SAFETY: only integers, any bit pattern is a record. `magic` tells
+// a written one from leftovers.
+unsafe impl esp_hal::Persistable for Record {}
+
+/// Written by the panic handler, kept over the reset that follows.
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut RECORD: Record = Record {
+    magic: 0,
+    message_len: 0,
+    location_len: 0,
+    frame_count: 0,
+    uptime_ms: 0,
+    message: [0; MESSAGE_LEN],
+    location: [0; LOCATION_LEN],
+    frames: [0; FRAMES],
+};
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+/// The crash reported at boot, until it is saved.
+static LAST: Mutex<CriticalSectionRawMutex, RefCell<Option<Crash>>> = Mutex::new(RefCell::new(None));
+
+#[derive(Clone, Debug)]
+pub struct Crash {
+    pub message: String,
+    /// `file:line` of the panic, empty if unknown.
+    pub location: String,
+    pub uptime_ms: u64,
+    /// Likely return addresses, newest first. Resolve with `addr2line`.
+    pub frames: Vec<u32>,
+}
+
+impl Crash {
+    pub fn summary(&self) -> String {
+        if self.location.is_empty() {
+            self.message.clone()
+        } else {
+            format!("{} at {}", self.message, self.location)
+        }
+    }
+
+    /// One line of the crash log.
+    pub fn to_log_line(&self) -> String {
+        let mut line = format!("uptime {} ms, {}, backtrace", self.uptime_ms, self.summary());
+        for frame in &self.frames {
+            write!(line, " 0x{:08x}", frame).ok();
+        }
+        line
+    }
+}
+
+/// Called from the panic handler, logs the panic and keeps it for the
+/// next boot. The caller resets the chip afterwards.
+pub fn record(info: &PanicInfo) {
+    // a panic while recording must not recurse
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let mut message = Truncated::<MESSAGE_LEN>::new();
+    write!(message, "{}", info.message()).ok();
+    let mut location = Truncated::<LOCATION_LEN>::new();
+    if let Some(loc) = info.location() {
+        write!(location, "{}:{}", loc.file(), loc.line()).ok();
+    }
+    error!("Panic at {}: {}", location.as_str(), message.as_str());
+
+    let (frames, frame_count) = return_addresses();
+    store(&message, &location, frames, frame_count);
+}
+
+/// Keep `reason` for the next boot the same way as a panic, for resets
+/// done on purpose. The caller resets the chip afterwards.
+pub fn record_reset(reason: &str) {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let mut message = Truncated::<MESSAGE_LEN>::new();
+    message.write_str(reason).ok();
+    store(&message, &Truncated::new(), [0; FRAMES], 0);
+}
+
+fn store(
+    message: &Truncated<MESSAGE_LEN>,
+    location: &Truncated<LOCATION_LEN>,
+    frames: [u32; FRAMES],
+    frame_count: usize,
+) {
+    let record = Record {
+        magic: RECORD_MAGIC,
+        message_len: message.len as u32,
+        location_len: location.len as u32,
+        frame_count: frame_count as u32,
+        uptime_ms: esp_hal::time::Instant::now().duration_since_epoch().as_millis(),
+        message: message.buf,
+        location: location.buf,
+        frames,
+    };
+    // SAFETY: written once, right before the reset, see `PANICKING`
+    unsafe { (&raw mut RECORD).write_volatile(record) };
+}
+
+/// The crash recorded before the last reset, cleared once read.
+pub fn take_last() -> Option<Crash> {
+    // SAFETY: read once at boot, only the panic handler writes it
+    let record = unsafe { (&raw const RECORD).read_volatile() };
+    if record.magic != RECORD_MAGIC {
+        return None;
+    }
+    // SAFETY: as above
+    unsafe { (&raw mut RECORD.magic).write_volatile(0) };
+
+    let message_len = (record.message_len as usize).min(MESSAGE_LEN);
+    let location_len = (record.location_len as usize).min(LOCATION_LEN);
+    let frame_count = (record.frame_count as usize).min(FRAMES);
+    Some(Crash {
+        message: String::from_utf8_lossy(&record.message[..message_len]).into_owned(),
+        location: String::from_utf8_lossy(&record.location[..location_len]).into_owned(),
+        uptime_ms: record.uptime_ms,
+        frames: record.frames[..frame_count].to_vec(),
+    })
+}
+
+/// Append the crash reported at boot to `LOG_FILE`. False if there is
+/// none or it could not be written, in which case it is kept to try
+/// again.
+pub async fn save() -> bool {
+    let Some(line) = LAST.lock(|last| last.borrow().as_ref().map(Crash::to_log_line)) else {
+        return false;
+    };
+    if !storage::append_line_confirmed(LOG_FILE, line).await {
+        return false;
+    }
+    LAST.lock(|last| last.borrow_mut().take());
+    true
+}
+
+/// Shows the crash before the last reset, if there was one, and offers
+/// to save it.
+#[embassy_executor::task]
+pub async fn crash_report_task() {
+    let Some(crash) = take_last() else {
+        return;
+    };
+    info!("Last crash: {}", crash.to_log_line().as_str());
+    let summary = crash.summary();
+    LAST.lock(|last| last.replace(Some(crash)));
+
+    // after the self test, which would cover it
+    health::REPORTED.wait().await;
+    top_bar::show(TopBarMode::Alert { title: "LAST CRASH", detail: summary });
+    dialog::confirm("Last crash", String::from("Save it to the SD card?"), MenuCommand::SaveCrash).await;
+}
+
+/// Words on the stack above the panic handler that point into code,
+/// newest first. Xtensa keeps no frame chain that can be walked without
+/// spilling the register windows, so some of these may be stale.
+fn return_addresses() -> ([u32; FRAMES], usize) {
+    let mut frames = [0; FRAMES];
+    let mut count = 0;
+    let marker = 0u32;
+    let mut addr = &raw const marker as usize;
+    let end = (addr + STACK_SCAN_WORDS * 4).min(DRAM_END);
+
+    while addr < end && count < FRAMES {
+        // SAFETY: word aligned and inside internal RAM
+        let word = unsafe { (addr as *const u32).read_volatile() };
+        if let Some(pc) = code_address(word) {
+            frames[count] = pc;
+            count += 1;
+        }
+        addr += 4;
+    }
+    (frames, count)
+}
+
+/// Windowed calls keep the window size in the top two bits of a return
+/// address, put the code region back before checking it.
+fn code_address(word: u32) -> Option<u32> {
+    if word >> 30 == 0 {
+        return None;
+    }
+    let pc = (word & 0x3FFF_FFFF) | 0x4000_0000;
+    (IRAM.contains(&pc) || IROM.contains(&pc)).then_some(pc)
+}
+
+/// Text cut off at `N` bytes, on a character boundary.
+struct Truncated<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Truncated<N> {
+    fn new() -> Self {
+        Self { buf: [0; N], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> Write for Truncated<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.len + len > N {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.buf[self.len..self.len + len]);
+            self.len += len;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{with_deadline, Duration, Instant};
+use esp_radio::wifi::{PromiscuousPkt, Sniffer};
+use smart_leds::colors;
+use defmt::info;
+
+use alloc::format;
+use alloc::string::String;
+
+use bitband::deauth::{classify, parse_threshold, Flood, FloodDetector, FloodTarget, FrameKind};
+
+use crate::led::{self, LedSource, Pattern, Priority};
+use crate::rogue_ap::format_bssid;
+use crate::storage;
+use crate::top_bar::{self, TopBarMode};
+
+pub const CONFIG_FILE: &str = "DEAUTH.CFG";
+pub const LOG_FILE: &str = "DEAUTH.LOG";
+const DEFAULT_THRESHOLD: u32 = 20;
+const REFRESH_MS: u64 = 1000;
+
+#[derive(Copy, Clone)]
+struct SeenFrame {
+    kind: FrameKind,
+    channel: u8,
+    bssid: [u8; 6],
+}
+
+static FRAME_CH: Channel<CriticalSectionRawMutex, SeenFrame, 32> = Channel::new();
+
+/// The sniffer does not hop, it stays on the channel the radio is on.
+/// Taken from the last frame received, 0 before the first one.
+static LISTEN_CHANNEL: AtomicU8 = AtomicU8::new(0);
+
+static MONITOR_ENABLED: AtomicBool = AtomicBool::new(false);
+static MONITOR_WAKE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn monitor_enabled() -> bool {
+    MONITOR_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn toggle_monitor() {
+    let enabled = !MONITOR_ENABLED.load(Ordering::Relaxed);
+    MONITOR_ENABLED.store(enabled, Ordering::Relaxed);
+    MONITOR_WAKE.signal(());
+    info!("Deauth monitor {}", if enabled { "enabled" } else { "disabled" });
+}
+
+// Runs in the Wi-Fi driver context, keep it short
+fn on_frame(pkt: PromiscuousPkt<'_>) {
+    LISTEN_CHANNEL.store(pkt.rx_cntl.channel as u8, Ordering::Relaxed);
+    if let Some((kind, bssid)) = classify(pkt.data) {
+        let _ = FRAME_CH.try_send(SeenFrame {
+            kind,
+            channel: pkt.rx_cntl.channel as u8,
+            bssid,
+        });
+    }
+}
+
+#[embassy_executor::task]
+pub async fn deauth_task(sniffer: &'static mut Sniffer<'static>) {
+    sniffer.set_receive_cb(on_frame);
+
+    let mut detector = FloodDetector::new(DEFAULT_THRESHOLD);
+    let mut next_refresh = Instant::now();
+    let mut alarm = false;
+    let mut channel = 0;
+
+    loop {
+        if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+            MONITOR_WAKE.wait().await;
+            if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            let threshold = match storage::read_to_string(CONFIG_FILE).await {
+                Some(contents) => parse_threshold(&contents).unwrap_or(DEFAULT_THRESHOLD),
+                None => DEFAULT_THRESHOLD,
+            };
+            info!("Deauth monitor threshold: {} frames", threshold);
+
+            detector = FloodDetector::new(threshold);
+            FRAME_CH.clear();
+            LISTEN_CHANNEL.store(0, Ordering::Relaxed);
+            channel = 0;
+            if sniffer.set_promiscuous_mode(true).is_err() {
+                info!("Failed to enable promiscuous mode");
+            }
+            next_refresh = Instant::now();
+        }
+
+        match with_deadline(next_refresh, FRAME_CH.receive()).await {
+            Ok(frame) => {
+                let now_ms = Instant::now().as_millis();
+                let floods = detector.record(now_ms, frame.channel, frame.bssid);
+                if let Some(flood) = floods.first() {
+                    top_bar::show(TopBarMode::Alert {
+                        title: "DEAUTH FLOOD",
+                        detail: describe(flood, frame.channel),
+                    });
+                }
+                for flood in floods {
+                    report(flood, frame.kind, frame.channel).await;
+                }
+            }
+            Err(_) => {
+                next_refresh += Duration::from_millis(REFRESH_MS);
+
+                if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+                    let _ = sniffer.set_promiscuous_mode(false);
+                    led::clear(LedSource::Deauth);
+                    alarm = false;
+                    continue;
+                }
+
+                let total = detector.refresh(Instant::now().as_millis());
+                let flooding = detector.is_flooding();
+
+                let listening = LISTEN_CHANNEL.load(Ordering::Relaxed);
+                if listening != channel {
+                    info!("Deauth monitor listening on channel {}", listening);
+                    channel = listening;
+                }
+
+                if flooding != alarm {
+                    if flooding {
+                        led::request(
+                            LedSource::Deauth,
+                            Priority::Alarm,
+                            Pattern::Blink { color: colors::RED, on_ms: 80, off_ms: 80 },
+                            None,
+                        );
+                    } else {
+                        led::clear(LedSource::Deauth);
+                    }
+                    alarm = flooding;
+                }
+
+                top_bar::show(TopBarMode::DeauthMonitor {
+                    frames: total,
+                    flooding,
+                    channel,
+                });
+            }
+        }
+    }
+}
+
+/// One line for the top bar alert.
+fn describe(flood: &Flood, channel: u8) -> String {
+    match flood.target {
+        FloodTarget::Channel(_) => format!("{} frames on ch{}", flood.count, channel),
+        FloodTarget::Bssid(bssid) => format!("{} ch{} x{}", format_bssid(&bssid), channel, flood.count),
+    }
+}
+
+async fn report(flood: Flood, kind: FrameKind, channel: u8) {
+    let kind = match kind {
+        FrameKind::Deauth => "deauth",
+        FrameKind::Disassoc => "disassoc",
+    };
+    let line = match flood.target {
+        FloodTarget::Channel(channel) => format!(
+            "{} {}-flood ch={} count={}",
+            Instant::now().as_secs(), kind, channel, flood.count
+        ),
+        FloodTarget::Bssid(bssid) => format!(
+            "{} {}-flood bssid={} ch={} count={}",
+            Instant::now().as_secs(), kind, format_bssid(&bssid), channel, flood.count
+        ),
+    };
+
+    info!("Deauth monitor: {}", line.as_str());
+    storage::append_line(LOG_FILE, line).await;
+}
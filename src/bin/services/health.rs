@@ -0,0 +1,148 @@
+use core::cell::Cell;
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Timer};
+use defmt::info;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::dialog;
+use crate::top_bar::{self, TopBarMode};
+
+const SUBSYSTEMS: usize = 6;
+/// Leaves the boot notice of power_task up for a moment first.
+const REPORT_DELAY_MS: u64 = 1000;
+const REPORT_SECS: u64 = 4;
+
+/// Signalled once the self test report has been on screen for
+/// `REPORT_SECS`, so later boot reports do not cover it.
+pub static REPORTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Hardware brought up at boot, each on its own.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Subsystem {
+    TopDisplay,
+    BottomDisplay,
+    Wifi,
+    Bluetooth,
+    Led,
+    SdCard,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; SUBSYSTEMS] = [
+        Subsystem::TopDisplay,
+        Subsystem::BottomDisplay,
+        Subsystem::Wifi,
+        Subsystem::Bluetooth,
+        Subsystem::Led,
+        Subsystem::SdCard,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::TopDisplay => "Top display",
+            Subsystem::BottomDisplay => "Bottom display",
+            Subsystem::Wifi => "WiFi",
+            Subsystem::Bluetooth => "Bluetooth",
+            Subsystem::Led => "LED",
+            Subsystem::SdCard => "SD card",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Health {
+    /// Not brought up yet.
+    Unknown,
+    Ok,
+    /// Did not answer, most likely not fitted.
+    Absent,
+    /// The board has none.
+    NotFitted,
+    Error(&'static str),
+}
+
+impl Health {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Health::Unknown => "?",
+            Health::Ok => "ok",
+            Health::Absent => "absent",
+            Health::NotFitted => "not fitted",
+            Health::Error(reason) => reason,
+        }
+    }
+
+    /// Nothing to report in the self test.
+    pub fn is_fine(&self) -> bool {
+        matches!(self, Health::Ok | Health::NotFitted)
+    }
+}
+
+static HEALTH: Mutex<CriticalSectionRawMutex, Cell<[Health; SUBSYSTEMS]>> =
+    Mutex::new(Cell::new([Health::Unknown; SUBSYSTEMS]));
+
+pub fn set(subsystem: Subsystem, health: Health) {
+    let changed = HEALTH.lock(|h| {
+        let mut all = h.get();
+        let changed = all[subsystem as usize] != health;
+        all[subsystem as usize] = health;
+        h.set(all);
+        changed
+    });
+    if changed {
+        info!("{}: {}", subsystem.name(), health.describe());
+    }
+}
+
+pub fn get(subsystem: Subsystem) -> Health {
+    HEALTH.lock(|h| h.get()[subsystem as usize])
+}
+
+pub fn ok(subsystem: Subsystem) -> bool {
+    get(subsystem) == Health::Ok
+}
+
+/// One line for the self test: the first problem and how many more
+/// there are.
+pub fn summary() -> String {
+    let failed: Vec<(Subsystem, Health)> = Subsystem::ALL
+        .iter()
+        .map(|&s| (s, get(s)))
+        .filter(|(_, health)| !health.is_fine())
+        .collect();
+
+    match failed.as_slice() {
+        [] => {
+            let fitted = Subsystem::ALL.iter().filter(|&&s| get(s) != Health::NotFitted).count();
+            format!("All {} ok", fitted)
+        }
+        [(subsystem, health)] => format!("{} {}", subsystem.name(), health.describe()),
+        [(subsystem, health), rest @ ..] => {
+            format!("{} {}, +{} more", subsystem.name(), health.describe(), rest.len())
+        }
+    }
+}
+
+/// Shows the outcome of the boot sequence once the UI is up. Problems
+/// stay on screen longer, details are under System > Hardware.
+#[embassy_executor::task]
+pub async fn self_test_task() {
+    Timer::after_millis(REPORT_DELAY_MS).await;
+
+    let summary = summary();
+    info!("Self test: {}", summary.as_str());
+    if Subsystem::ALL.iter().all(|&s| get(s).is_fine()) {
+        top_bar::show(TopBarMode::Notice { title: "Self test", detail: summary });
+    } else {
+        dialog::alert("Self test", summary, Duration::from_secs(REPORT_SECS)).await;
+    }
+    Timer::after_secs(REPORT_SECS).await;
+    REPORTED.signal(());
+}
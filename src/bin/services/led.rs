@@ -0,0 +1,189 @@
+use core::cell::Cell;
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use embassy_futures::select::{select, Either};
+use smart_leds::{brightness, colors, SmartLedsWrite, RGB8};
+use defmt::info;
+
+use bitband::led::{frame_at, next_change};
+pub use bitband::led::{Pattern, Priority};
+
+use crate::app_state::{self, RadioState};
+use crate::battery_policy::BatteryLevel;
+use crate::settings;
+
+/// Longest the LED sleeps on a steady pattern, so brightness changes
+/// show up without a request.
+const IDLE_MS: u32 = 1000;
+/// Drive level at 100% brightness, the WS2812 is blinding above this.
+const LEVEL_MAX: u8 = 40;
+
+/// Who asked for an indication. Each source holds at most one request,
+/// a new one replaces the previous.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LedSource {
+    Keepalive,
+    Wifi,
+    Battery,
+    RogueAp,
+    Deauth,
+    Notification,
+    /// Keeps the LED dark while the device sleeps.
+    Power,
+}
+
+const SOURCES: usize = 7;
+
+impl LedSource {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Active {
+    priority: Priority,
+    pattern: Pattern,
+    started: Instant,
+    expires: Option<Instant>,
+}
+
+/// The current request of every source. A slot is overwritten in place,
+/// so a clear can not get lost behind a full queue.
+static ACTIVE: Mutex<CriticalSectionRawMutex, Cell<[Option<Active>; SOURCES]>> =
+    Mutex::new(Cell::new([None; SOURCES]));
+static CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+fn update(f: impl FnOnce(&mut [Option<Active>; SOURCES])) {
+    ACTIVE.lock(|active| {
+        let mut table = active.get();
+        f(&mut table);
+        active.set(table);
+    });
+    CHANGED.signal(());
+}
+
+/// Show `pattern` on behalf of `source` until cleared or `timeout`
+/// expires.
+pub fn request(source: LedSource, priority: Priority, pattern: Pattern, timeout: Option<Duration>) {
+    let now = Instant::now();
+    update(|active| {
+        let slot = &mut active[source.index()];
+        // keep the phase if the same pattern is requested again
+        let started = match slot {
+            Some(a) if a.pattern == pattern => a.started,
+            _ => now,
+        };
+        *slot = Some(Active {
+            priority,
+            pattern,
+            started,
+            expires: timeout.map(|t| now + t),
+        });
+    });
+}
+
+pub fn clear(source: LedSource) {
+    update(|active| active[source.index()] = None);
+}
+
+/// Shows low battery and running scans, following the app state.
+#[embassy_executor::task]
+pub async fn state_task() {
+    let mut battery = app_state::BATTERY_LEVEL.receiver().unwrap();
+    let mut radio = app_state::RADIO.receiver().unwrap();
+
+    show_battery(battery.get().await);
+    show_radio(radio.get().await);
+
+    loop {
+        match select(battery.changed(), radio.changed()).await {
+            Either::First(state) => show_battery(state),
+            Either::Second(state) => show_radio(state),
+        }
+    }
+}
+
+fn show_battery(level: BatteryLevel) {
+    let pattern = match level {
+        BatteryLevel::Ok => return clear(LedSource::Battery),
+        BatteryLevel::Low => Pattern::Breathe { color: colors::ORANGE, period_ms: 2000 },
+        BatteryLevel::Shedding => Pattern::Breathe { color: colors::ORANGE, period_ms: 1000 },
+        BatteryLevel::Critical => Pattern::Blink { color: colors::RED, on_ms: 100, off_ms: 900 },
+    };
+    request(LedSource::Battery, Priority::Warning, pattern, None);
+}
+
+fn show_radio(radio: RadioState) {
+    match radio {
+        RadioState::Scanning => request(
+            LedSource::Wifi,
+            Priority::Status,
+            Pattern::Blink { color: colors::BLUE, on_ms: 100, off_ms: 400 },
+            Some(Duration::from_secs(10)),
+        ),
+        RadioState::Idle => clear(LedSource::Wifi),
+    }
+}
+
+/// Pick the highest priority request, the most recent one on a tie.
+fn winner(active: &[Option<Active>]) -> Option<&Active> {
+    active
+        .iter()
+        .flatten()
+        .max_by_key(|a| (a.priority, a.started))
+}
+
+/// Drives the status LED from the requests. Runs on the task that owns
+/// the LED adapter, the RMT pulse buffer usually lives on its stack.
+/// Sleeps until the shown pattern changes, a request expires or a new
+/// one comes in.
+pub async fn run<L>(mut led: L) -> !
+where
+    L: SmartLedsWrite<Color = RGB8>,
+{
+    let mut last: Option<(RGB8, u8)> = None;
+
+    request(LedSource::Keepalive, Priority::Idle, Pattern::Rainbow { period_ms: 3000 }, None);
+
+    info!("LED service started");
+
+    loop {
+        let now = Instant::now();
+
+        let active = ACTIVE.lock(|table| {
+            let mut active = table.get();
+            for slot in active.iter_mut() {
+                if matches!(slot, Some(Active { expires: Some(at), .. }) if *at <= now) {
+                    *slot = None;
+                }
+            }
+            table.set(active);
+            active
+        });
+
+        let (color, change_ms) = match winner(&active) {
+            Some(a) => {
+                let elapsed = (now - a.started).as_millis() as u32;
+                (frame_at(&a.pattern, elapsed), next_change(&a.pattern, elapsed))
+            }
+            None => (colors::BLACK, None),
+        };
+
+        let level = (settings::brightness() as u16 * LEVEL_MAX as u16 / 100) as u8;
+        if last != Some((color, level)) {
+            let _ = led.write(brightness([color].into_iter(), level));
+            last = Some((color, level));
+        }
+
+        let mut wake = now + Duration::from_millis(change_ms.unwrap_or(IDLE_MS).min(IDLE_MS) as u64);
+        for expires in active.iter().flatten().filter_map(|a| a.expires) {
+            wake = wake.min(expires);
+        }
+        select(CHANGED.wait(), Timer::at(wake)).await;
+    }
+}
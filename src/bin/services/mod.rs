@@ -1,2 +1,14 @@
+pub mod app_state;
 pub mod battery;
+pub mod battery_policy;
+pub mod bus;
 pub mod clock;
+pub mod crash;
+pub mod deauth;
+pub mod health;
+pub mod led;
+pub mod power;
+pub mod rogue_ap;
+pub mod settings;
+pub mod storage;
+pub mod watchdog;
@@ -0,0 +1,230 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use embassy_futures::select::{select, Either};
+use esp_hal::gpio::{AnyPin, RtcPinWithResistors};
+use esp_hal::rtc_cntl::{
+    sleep::{Ext0WakeupSource, GpioWakeupSource, TimerWakeupSource, WakeupLevel},
+    Rtc, SocResetReason,
+};
+use esp_hal::system::SleepSource;
+use defmt::info;
+
+use alloc::string::String;
+
+use crate::app_state::{self, RadioState};
+use crate::board::BOARD;
+use crate::clock;
+use crate::deauth;
+use crate::led::{self, LedSource, Pattern, Priority};
+use crate::rogue_ap;
+use crate::screen;
+use crate::top_bar::{self, TopBarMode};
+use crate::watchdog;
+
+use bitband::power::{decide, PowerAction};
+
+const POLL_MS: u64 = 1000;
+/// Longest light sleep, so the clock and battery are still looked at.
+const LIGHT_SLEEP_SECS: u64 = 5;
+/// Deep sleep after this long without a button press.
+const DEEP_SLEEP_AFTER_SECS: u64 = 10 * 60;
+/// Wake from an idle deep sleep this often to check the battery.
+const DEEP_SLEEP_WAKE_SECS: u64 = 6 * 60 * 60;
+/// Time for the LED and panels to go dark before sleeping.
+const SETTLE_MS: u64 = 600;
+const RECORD_MAGIC: u32 = 0xB17B_A4D0;
+
+/// Written before deep sleep so the clock survives it.
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut RECORD_VALID: u32 = 0;
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut RECORD_SECS_OF_DAY: u32 = 0;
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut RECORD_RTC_MS: u64 = 0;
+
+static POWER_OFF: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BootReason {
+    PowerOn,
+    /// Woken from deep sleep by Select.
+    Button,
+    /// Woken from deep sleep by the timer.
+    Timer,
+    Watchdog,
+    BrownOut,
+    Reset,
+}
+
+impl BootReason {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            BootReason::PowerOn => "power on",
+            BootReason::Button => "woken by button",
+            BootReason::Timer => "woken by timer",
+            BootReason::Watchdog => "watchdog reset",
+            BootReason::BrownOut => "brown-out",
+            BootReason::Reset => "reset",
+        }
+    }
+}
+
+pub fn boot_reason() -> BootReason {
+    match esp_hal::system::reset_reason() {
+        Some(SocResetReason::CoreDeepSleep) => match esp_hal::system::wakeup_cause() {
+            SleepSource::Timer => BootReason::Timer,
+            _ => BootReason::Button,
+        },
+        None | Some(SocResetReason::ChipPowerOn) => BootReason::PowerOn,
+        Some(SocResetReason::SysBrownOut) => BootReason::BrownOut,
+        Some(
+            SocResetReason::CoreMwdt0
+            | SocResetReason::CoreMwdt1
+            | SocResetReason::CoreRtcWdt
+            | SocResetReason::CpuMwdt0
+            | SocResetReason::CpuMwdt1
+            | SocResetReason::CpuRtcWdt
+            | SocResetReason::SysRtcWdt,
+        ) => BootReason::Watchdog,
+        Some(_) => BootReason::Reset,
+    }
+}
+
+/// Switch off from the menu, only Select brings the device back. On a
+/// board without a wake pin the panels go off and the device light
+/// sleeps until any button.
+pub fn power_off() {
+    POWER_OFF.signal(());
+}
+
+fn radio_busy() -> bool {
+    app_state::get().radio != RadioState::Idle
+        || rogue_ap::monitor_enabled()
+        || deauth::monitor_enabled()
+}
+
+/// Sleeps whenever the screen is off and nothing needs the radio.
+#[embassy_executor::task]
+pub async fn power_task(mut rtc: Rtc<'static>) {
+    let reason = boot_reason();
+    info!("Boot reason: {}", reason.describe());
+
+    if matches!(reason, BootReason::Button | BootReason::Timer) {
+        restore_clock(&rtc);
+    }
+    // SAFETY: only this task touches the record
+    unsafe { (&raw mut RECORD_VALID).write_volatile(0) };
+
+    if reason != BootReason::PowerOn {
+        top_bar::show(TopBarMode::Notice {
+            title: "Boot",
+            detail: String::from(reason.describe()),
+        });
+    }
+
+    // `Instant` may stand still in light sleep. What it lost is added
+    // back to the time of day and to the idle time.
+    let mut lost_ms: u64 = 0;
+    let mut lost_idle_ms: u64 = 0;
+    let mut dark = false;
+
+    loop {
+        if let Either::First(()) = select(POWER_OFF.wait(), Timer::after_millis(POLL_MS)).await {
+            match BOARD.wake_pin {
+                Some(pin) => deep_sleep(&mut rtc, pin, false).await,
+                None => screen::sleep(),
+            }
+        }
+
+        let idle = screen::idle().as_secs() + lost_idle_ms / 1000;
+        // without a wake pin nothing but the timer could end a deep sleep
+        let deep_after = if BOARD.wake_pin.is_some() { DEEP_SLEEP_AFTER_SECS } else { 0 };
+        match decide(screen::state(), radio_busy(), idle, deep_after) {
+            PowerAction::Stay => {
+                lost_idle_ms = 0;
+                if dark {
+                    led::clear(LedSource::Power);
+                    dark = false;
+                }
+            }
+            PowerAction::LightSleep => {
+                if !dark {
+                    led::request(LedSource::Power, Priority::Alarm, Pattern::Off, None);
+                    Timer::after_millis(SETTLE_MS).await;
+                    dark = true;
+                }
+
+                let rtc_before = rtc.time_since_boot().as_millis();
+                let before = Instant::now();
+                // any button wakes, see `wakeup_enable` in main
+                let timer = TimerWakeupSource::new(core::time::Duration::from_secs(LIGHT_SLEEP_SECS));
+                rtc.sleep_light(&[&timer, &GpioWakeupSource::new()]);
+                watchdog::excuse();
+
+                let slept = rtc.time_since_boot().as_millis() - rtc_before;
+                let lost = slept.saturating_sub(before.elapsed().as_millis());
+                lost_ms += lost;
+                lost_idle_ms += lost;
+                if lost_ms >= 1000 {
+                    clock::adjust((lost_ms / 1000) as i32);
+                    lost_ms %= 1000;
+                }
+            }
+            PowerAction::DeepSleep => {
+                if let Some(pin) = BOARD.wake_pin {
+                    deep_sleep(&mut rtc, pin, true).await;
+                }
+            }
+        }
+    }
+}
+
+/// `wake_pin` is Select, on an RTC pin, see `board`.
+async fn deep_sleep(rtc: &mut Rtc<'static>, wake_pin: u8, timer_wake: bool) -> ! {
+    info!("Deep sleep, timer wake {}", timer_wake);
+
+    // SAFETY: only this task touches the record
+    unsafe {
+        (&raw mut RECORD_SECS_OF_DAY).write_volatile(clock::now_secs_of_day());
+        (&raw mut RECORD_RTC_MS).write_volatile(rtc.time_since_boot().as_millis());
+        (&raw mut RECORD_VALID).write_volatile(RECORD_MAGIC);
+    }
+
+    screen::sleep();
+    led::request(LedSource::Power, Priority::Alarm, Pattern::Off, None);
+    Timer::after(Duration::from_millis(SETTLE_MS)).await;
+
+    // SAFETY: the driver is still held by button_task, nothing runs
+    // after this though
+    let wake_pin = unsafe { AnyPin::steal(wake_pin) };
+    // the digital pull-up is off in deep sleep
+    wake_pin.rtcio_pullup(true);
+    let button = Ext0WakeupSource::new(wake_pin, WakeupLevel::Low);
+    let timer = TimerWakeupSource::new(core::time::Duration::from_secs(DEEP_SLEEP_WAKE_SECS));
+
+    if timer_wake {
+        rtc.sleep_deep(&[&button, &timer])
+    } else {
+        rtc.sleep_deep(&[&button])
+    }
+}
+
+/// Set the time of day from the record written before deep sleep.
+fn restore_clock(rtc: &Rtc<'static>) {
+    // SAFETY: read once at boot, before anything writes it
+    let (valid, secs_of_day, rtc_ms) = unsafe {
+        (
+            (&raw const RECORD_VALID).read_volatile(),
+            (&raw const RECORD_SECS_OF_DAY).read_volatile(),
+            (&raw const RECORD_RTC_MS).read_volatile(),
+        )
+    };
+    if valid != RECORD_MAGIC {
+        return;
+    }
+
+    let asleep_secs = rtc.time_since_boot().as_millis().saturating_sub(rtc_ms) / 1000;
+    let now = (secs_of_day as u64 + asleep_secs) % (24 * 60 * 60);
+    clock::adjust(now as i32 - clock::now_secs_of_day() as i32);
+    info!("Clock restored after {} s of deep sleep", asleep_secs);
+}
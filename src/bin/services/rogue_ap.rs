@@ -0,0 +1,110 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use smart_leds::colors;
+use defmt::info;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use bitband::rogue_ap::{detect, parse_allowlist, Incident};
+pub use bitband::rogue_ap::{format_bssid, Auth, Sighting};
+
+use crate::led::{self, LedSource, Pattern, Priority};
+use crate::menu::{self, WifiScanRequest};
+use crate::storage;
+use crate::top_bar::{self, TopBarMode};
+
+pub const ALLOWLIST_FILE: &str = "ALLOW.CSV";
+pub const LOG_FILE: &str = "ROGUE.LOG";
+const SCAN_INTERVAL_SECS: u64 = 20;
+
+static MONITOR_ENABLED: AtomicBool = AtomicBool::new(false);
+static MONITOR_WAKE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub static SCAN_RESULT_CH: Channel<
+    CriticalSectionRawMutex,
+    Vec<Sighting>,
+    1,
+> = Channel::new();
+
+pub fn monitor_enabled() -> bool {
+    MONITOR_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn toggle_monitor() {
+    let enabled = !MONITOR_ENABLED.load(Ordering::Relaxed);
+    MONITOR_ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        led::clear(LedSource::RogueAp);
+    }
+    MONITOR_WAKE.signal(());
+    info!("Rogue AP monitor {}", if enabled { "enabled" } else { "disabled" });
+}
+
+#[embassy_executor::task]
+pub async fn rogue_ap_task() {
+    let mut allowlist = Vec::new();
+    let mut reported: Vec<Incident> = Vec::new();
+
+    loop {
+        if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+            MONITOR_WAKE.wait().await;
+            if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            // reload on every enable so edits to the card are picked up
+            allowlist = match storage::read_to_string(ALLOWLIST_FILE).await {
+                Some(contents) => parse_allowlist(&contents),
+                None => Vec::new(),
+            };
+            reported.clear();
+            info!("Rogue AP allowlist: {} entries", allowlist.len());
+        }
+
+        menu::WIFI_SCAN_CH.send(WifiScanRequest::RogueMonitor).await;
+        let scan = SCAN_RESULT_CH.receive().await;
+        // switched off during the scan, which already cleared the LED
+        if !MONITOR_ENABLED.load(Ordering::Relaxed) {
+            continue;
+        }
+        let incidents = detect(&allowlist, &scan);
+
+        // keep the alarm up while the last scan had incidents
+        if incidents.is_empty() {
+            led::clear(LedSource::RogueAp);
+        } else {
+            led::request(
+                LedSource::RogueAp,
+                Priority::Alarm,
+                Pattern::Blink { color: colors::RED, on_ms: 150, off_ms: 150 },
+                None,
+            );
+        }
+
+        for incident in incidents.iter().filter(|i| !reported.contains(i)) {
+            let line = incident.describe();
+            info!("Rogue AP: {}", line.as_str());
+
+            storage::append_line(
+                LOG_FILE,
+                format!("{} {}", Instant::now().as_secs(), line),
+            ).await;
+
+            top_bar::show(TopBarMode::Alert {
+                title: "ROGUE AP",
+                detail: String::from(incident.ssid()),
+            });
+        }
+        reported = incidents;
+
+        Timer::after(Duration::from_secs(SCAN_INTERVAL_SECS)).await;
+    }
+}
@@ -0,0 +1,230 @@
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Timer};
+use defmt::info;
+
+use alloc::format;
+use alloc::string::String;
+
+use crate::battery_policy::BatteryLevel;
+use crate::clock;
+use crate::storage;
+use crate::widget::BarLayout;
+
+use bitband::battery_policy::Thresholds;
+
+pub const SETTINGS_FILE: &str = "SETTINGS.CFG";
+const SAVE_DELAY_SECS: u64 = 2;
+const NIGHT_BRIGHTNESS_PERCENT: u8 = 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Settings {
+    /// Global brightness for both panels and the LED, in percent.
+    pub brightness: u8,
+    pub night_mode: bool,
+    pub night_start_hour: u8,
+    pub night_end_hour: u8,
+    /// Idle time before the panels switch off, 0 keeps them on.
+    pub screen_timeout_secs: u16,
+    pub top_bar: BarLayout,
+    /// Charge in percent at which the battery policy warns, switches
+    /// the radios off and shuts down.
+    pub battery_warn_percent: u8,
+    pub battery_shed_percent: u8,
+    pub battery_critical_percent: u8,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Self {
+            brightness: 25,
+            night_mode: false,
+            night_start_hour: 22,
+            night_end_hour: 7,
+            screen_timeout_secs: 30,
+            top_bar: BarLayout::new(),
+            battery_warn_percent: 20,
+            battery_shed_percent: 10,
+            battery_critical_percent: 3,
+        }
+    }
+
+    /// Parse `key=value` lines on top of the defaults. Unknown keys and
+    /// bad values are ignored so older files keep loading.
+    pub fn parse(contents: &str) -> Self {
+        let mut settings = Self::new();
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "brightness" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.brightness = v.clamp(1, 100);
+                    }
+                }
+                "night_mode" => settings.night_mode = value == "1",
+                "night_start" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.night_start_hour = v % 24;
+                    }
+                }
+                "night_end" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.night_end_hour = v % 24;
+                    }
+                }
+                "screen_timeout" => {
+                    if let Ok(v) = value.parse::<u16>() {
+                        settings.screen_timeout_secs = v;
+                    }
+                }
+                "top_bar" => settings.top_bar = BarLayout::parse(value),
+                "battery_warn" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.battery_warn_percent = v.min(100);
+                    }
+                }
+                "battery_shed" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.battery_shed_percent = v.min(100);
+                    }
+                }
+                "battery_critical" => {
+                    if let Ok(v) = value.parse::<u8>() {
+                        settings.battery_critical_percent = v.min(100);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // a hand-edited file may have them out of order
+        settings.set_battery_threshold(BatteryLevel::Low, settings.battery_warn_percent);
+        settings
+    }
+
+    pub fn battery_thresholds(&self) -> Thresholds {
+        Thresholds {
+            warn: self.battery_warn_percent,
+            shed: self.battery_shed_percent,
+            critical: self.battery_critical_percent,
+        }
+    }
+
+    /// Set one battery threshold, moving the others so the policy levels
+    /// stay in order.
+    pub fn set_battery_threshold(&mut self, level: BatteryLevel, percent: u8) {
+        let mut limits = self.battery_thresholds();
+        limits.set(level, percent);
+        self.battery_warn_percent = limits.warn;
+        self.battery_shed_percent = limits.shed;
+        self.battery_critical_percent = limits.critical;
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "brightness={}\nnight_mode={}\nnight_start={}\nnight_end={}\nscreen_timeout={}\ntop_bar={}\n\
+             battery_warn={}\nbattery_shed={}\nbattery_critical={}\n",
+            self.brightness,
+            self.night_mode as u8,
+            self.night_start_hour,
+            self.night_end_hour,
+            self.screen_timeout_secs,
+            self.top_bar.serialize(),
+            self.battery_warn_percent,
+            self.battery_shed_percent,
+            self.battery_critical_percent,
+        )
+    }
+
+    /// Brightness to use at `hour`, taking the night schedule into account.
+    pub fn effective_brightness(&self, hour: u8) -> u8 {
+        if self.night_mode && in_window(hour, self.night_start_hour, self.night_end_hour) {
+            self.brightness.min(NIGHT_BRIGHTNESS_PERCENT)
+        } else {
+            self.brightness
+        }
+    }
+}
+
+/// True if `hour` is in `[start, end)`, wrapping past midnight.
+pub fn in_window(hour: u8, start: u8, end: u8) -> bool {
+    if start <= end {
+        hour >= start && hour < end
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+static SETTINGS: Mutex<CriticalSectionRawMutex, Cell<Settings>> =
+    Mutex::new(Cell::new(Settings::new()));
+static BRIGHTNESS: AtomicU8 = AtomicU8::new(Settings::new().brightness);
+static SAVE_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Set while there are changes that have not been written yet.
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+pub fn get() -> Settings {
+    SETTINGS.lock(|s| s.get())
+}
+
+/// Change the settings and schedule them to be written to the SD card.
+pub fn update(f: impl FnOnce(&mut Settings)) {
+    SETTINGS.lock(|s| {
+        let mut settings = s.get();
+        f(&mut settings);
+        s.set(settings);
+    });
+    refresh_brightness();
+    DIRTY.store(true, Ordering::Relaxed);
+    SAVE_SIGNAL.signal(());
+}
+
+/// Current brightness in percent, after night mode.
+pub fn brightness() -> u8 {
+    BRIGHTNESS.load(Ordering::Relaxed)
+}
+
+pub fn refresh_brightness() {
+    let (hour, _) = clock::now_hhmm();
+    BRIGHTNESS.store(get().effective_brightness(hour), Ordering::Relaxed);
+}
+
+#[embassy_executor::task]
+pub async fn settings_task() {
+    if let Some(contents) = storage::read_to_string(SETTINGS_FILE).await {
+        let loaded = Settings::parse(&contents);
+        SETTINGS.lock(|s| s.set(loaded));
+        refresh_brightness();
+        info!("Settings loaded");
+    }
+
+    loop {
+        SAVE_SIGNAL.wait().await;
+        // batch up quick successive changes into one write
+        Timer::after(Duration::from_secs(SAVE_DELAY_SECS)).await;
+        SAVE_SIGNAL.reset();
+
+        save().await;
+    }
+}
+
+/// Write pending changes now instead of after the save delay.
+pub async fn flush() {
+    save().await;
+}
+
+async fn save() {
+    if DIRTY.swap(false, Ordering::Relaxed) {
+        storage::write_file(SETTINGS_FILE, get().serialize()).await;
+        info!("Settings saved");
+    }
+}
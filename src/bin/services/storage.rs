@@ -0,0 +1,270 @@
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    mutex::Mutex,
+    signal::Signal,
+};
+use embedded_hal_bus::spi::RefCellDevice;
+use embedded_sdmmc::{Mode, SdCard, TimeSource, VolumeIdx, VolumeManager};
+use esp_hal::{delay::Delay, gpio::Output, spi::{self, master::Spi}, time::Rate, Blocking};
+use defmt::info;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::health::{self, Health, Subsystem};
+use crate::watchdog::{self, Task};
+
+type SdSpi = RefCellDevice<'static, Spi<'static, Blocking>, Output<'static>, Delay>;
+pub type SdVolumeManager = VolumeManager<SdCard<SdSpi, Delay>, DummyTime>;
+
+const READ_CHUNK: usize = 64;
+
+pub enum StorageRequest {
+    /// `done`, if given, is signalled with whether the line was written.
+    AppendLine {
+        file: &'static str,
+        line: String,
+        done: Option<&'static Signal<CriticalSectionRawMutex, bool>>,
+    },
+    Write { file: &'static str, contents: String },
+    Read { file: &'static str },
+    Delete { file: &'static str },
+    /// Stop using the card once the requests before it are done.
+    Unmount,
+}
+
+pub static STORAGE_CH: Channel<
+    CriticalSectionRawMutex,
+    StorageRequest,
+    4,
+> = Channel::new();
+
+static APPEND_RESULT: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+static APPEND_LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+static READ_RESULT: Signal<CriticalSectionRawMutex, Option<String>> = Signal::new();
+static READ_LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+static DELETE_RESULT: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+static DELETE_LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+static UNMOUNTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static CARD_OK: AtomicBool = AtomicBool::new(false);
+
+/// True if the card could be opened the last time it was used.
+pub fn card_ok() -> bool {
+    CARD_OK.load(Ordering::Relaxed)
+}
+
+/// Queue a line to be appended to `file` in the SD card root directory.
+pub async fn append_line(file: &'static str, line: String) {
+    STORAGE_CH.send(StorageRequest::AppendLine { file, line, done: None }).await;
+}
+
+/// Append a line to `file` in the SD card root directory once the
+/// requests queued before it are done. Returns false if it could not be
+/// written.
+pub async fn append_line_confirmed(file: &'static str, line: String) -> bool {
+    let _guard = APPEND_LOCK.lock().await;
+    APPEND_RESULT.reset();
+    STORAGE_CH.send(StorageRequest::AppendLine { file, line, done: Some(&APPEND_RESULT) }).await;
+    APPEND_RESULT.wait().await
+}
+
+/// Queue `contents` to replace `file` in the SD card root directory.
+pub async fn write_file(file: &'static str, contents: String) {
+    STORAGE_CH.send(StorageRequest::Write { file, contents }).await;
+}
+
+/// Read a whole file from the SD card root directory.
+/// Returns `None` if the card, the file or its contents are unusable.
+pub async fn read_to_string(file: &'static str) -> Option<String> {
+    let _guard = READ_LOCK.lock().await;
+    READ_RESULT.reset();
+    STORAGE_CH.send(StorageRequest::Read { file }).await;
+    READ_RESULT.wait().await
+}
+
+/// Delete `file` from the SD card root directory once the requests
+/// queued before it are done. Returns false if it could not be deleted.
+pub async fn delete_file(file: &'static str) -> bool {
+    let _guard = DELETE_LOCK.lock().await;
+    DELETE_RESULT.reset();
+    STORAGE_CH.send(StorageRequest::Delete { file }).await;
+    DELETE_RESULT.wait().await
+}
+
+/// Finish everything queued so far and leave the card alone, so it can
+/// lose power safely. Requests after this fail.
+pub async fn unmount() {
+    STORAGE_CH.send(StorageRequest::Unmount).await;
+    UNMOUNTED.wait().await;
+}
+
+/// Set up the card on `spi_bus`. A missing card is only recorded, it
+/// is looked for again on every request and may still be inserted.
+pub fn init(spi_bus: Spi<'static, Blocking>, cs: Output<'static>) -> Option<&'static SdVolumeManager> {
+    let shared_spi_bus: &'static RefCell<_> = Box::leak(Box::new(RefCell::new(spi_bus)));
+    let spi_device = match RefCellDevice::new(shared_spi_bus, cs, Delay::new()) {
+        Ok(device) => device,
+        Err(_) => {
+            health::set(Subsystem::SdCard, Health::Error("SPI device"));
+            return None;
+        }
+    };
+
+    let sdcard = SdCard::new(spi_device, Delay::new());
+    match sdcard.num_bytes() {
+        Ok(bytes) => {
+            info!("SD card size: {} GiB", bytes / 1024 / 1024 / 1024);
+            health::set(Subsystem::SdCard, Health::Ok);
+        }
+        Err(_) => health::set(Subsystem::SdCard, Health::Absent),
+    }
+
+    let fast = spi::master::Config::default()
+        .with_frequency(Rate::from_mhz(2))
+        .with_mode(spi::Mode::_0);
+    if shared_spi_bus.borrow_mut().apply_config(&fast).is_err() {
+        info!("Failed to speed up the SD card, staying at 400 kHz");
+    }
+
+    Some(Box::leak(Box::new(VolumeManager::new(sdcard, DummyTime))))
+}
+
+/// Without a volume manager, because the SPI bus could not be set up,
+/// every request is turned down.
+#[embassy_executor::task]
+pub async fn storage_task(volume_mgr: Option<&'static SdVolumeManager>) {
+    let mut mounted = true;
+
+    loop {
+        watchdog::wait(Task::Storage);
+        let request = STORAGE_CH.receive().await;
+        watchdog::check_in(Task::Storage);
+        let volume_mgr = match volume_mgr {
+            Some(volume_mgr) if mounted => volume_mgr,
+            _ => {
+                match request {
+                    StorageRequest::AppendLine { done: Some(done), .. } => done.signal(false),
+                    StorageRequest::Read { .. } => READ_RESULT.signal(None),
+                    StorageRequest::Delete { .. } => DELETE_RESULT.signal(false),
+                    StorageRequest::Unmount => UNMOUNTED.signal(()),
+                    _ => info!("Card unavailable, request dropped"),
+                }
+                continue;
+            }
+        };
+
+        match request {
+            StorageRequest::AppendLine { file, line, done } => {
+                let appended = append(volume_mgr, file, &line).is_ok();
+                if !appended {
+                    info!("Failed to append to {}", file);
+                }
+                if let Some(done) = done {
+                    done.signal(appended);
+                }
+            }
+            StorageRequest::Write { file, contents } => {
+                if write(volume_mgr, file, &contents).is_err() {
+                    info!("Failed to write {}", file);
+                }
+            }
+            StorageRequest::Read { file } => {
+                let contents = read(volume_mgr, file).ok();
+                if contents.is_none() {
+                    info!("Failed to read {}", file);
+                }
+                READ_RESULT.signal(contents);
+            }
+            StorageRequest::Delete { file } => {
+                let deleted = delete(volume_mgr, file).is_ok();
+                if !deleted {
+                    info!("Failed to delete {}", file);
+                }
+                DELETE_RESULT.signal(deleted);
+            }
+            StorageRequest::Unmount => {
+                // every request closes what it opened, nothing is left
+                // to flush
+                mounted = false;
+                info!("Card unmounted");
+                UNMOUNTED.signal(());
+            }
+        }
+    }
+}
+
+fn track_card<T, E>(volume: Result<T, E>) -> Result<T, ()> {
+    let ok = volume.is_ok();
+    if CARD_OK.swap(ok, Ordering::Relaxed) != ok {
+        health::set(Subsystem::SdCard, if ok { Health::Ok } else { Health::Absent });
+    }
+    volume.map_err(|_| ())
+}
+
+fn append(volume_mgr: &SdVolumeManager, file: &str, line: &str) -> Result<(), ()> {
+    let volume = track_card(volume_mgr.open_volume(VolumeIdx(0)))?;
+    let root_dir = volume.open_root_dir().map_err(|_| ())?;
+    let file = root_dir
+        .open_file_in_dir(file, Mode::ReadWriteCreateOrAppend)
+        .map_err(|_| ())?;
+
+    file.write(line.as_bytes()).map_err(|_| ())?;
+    file.write(b"\n").map_err(|_| ())?;
+    file.close().map_err(|_| ())
+}
+
+fn write(volume_mgr: &SdVolumeManager, file: &str, contents: &str) -> Result<(), ()> {
+    let volume = track_card(volume_mgr.open_volume(VolumeIdx(0)))?;
+    let root_dir = volume.open_root_dir().map_err(|_| ())?;
+    let file = root_dir
+        .open_file_in_dir(file, Mode::ReadWriteCreateOrTruncate)
+        .map_err(|_| ())?;
+
+    file.write(contents.as_bytes()).map_err(|_| ())?;
+    file.close().map_err(|_| ())
+}
+
+fn read(volume_mgr: &SdVolumeManager, file: &str) -> Result<String, ()> {
+    let volume = track_card(volume_mgr.open_volume(VolumeIdx(0)))?;
+    let root_dir = volume.open_root_dir().map_err(|_| ())?;
+    let file = root_dir
+        .open_file_in_dir(file, Mode::ReadOnly)
+        .map_err(|_| ())?;
+
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; READ_CHUNK];
+    while !file.is_eof() {
+        let n = file.read(&mut chunk).map_err(|_| ())?;
+        bytes.extend_from_slice(&chunk[..n]);
+    }
+    file.close().map_err(|_| ())?;
+
+    String::from_utf8(bytes).map_err(|_| ())
+}
+
+fn delete(volume_mgr: &SdVolumeManager, file: &str) -> Result<(), ()> {
+    let volume = track_card(volume_mgr.open_volume(VolumeIdx(0)))?;
+    let root_dir = volume.open_root_dir().map_err(|_| ())?;
+    root_dir.delete_file_in_dir(file).map_err(|_| ())
+}
+
+// No RTC yet, every file gets the same timestamp
+pub struct DummyTime;
+
+impl TimeSource for DummyTime {
+    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
+        embedded_sdmmc::Timestamp {
+            year_since_1970: 54,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
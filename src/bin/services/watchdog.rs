@@ -0,0 +1,71 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Instant, Timer};
+use esp_hal::peripherals::TIMG1;
+use esp_hal::timer::timg::{MwdtStage, MwdtStageAction, Wdt};
+use defmt::{error, info};
+
+use alloc::format;
+
+use crate::crash;
+
+use bitband::watchdog::Heartbeats;
+pub use bitband::watchdog::Task;
+
+/// How often the supervisor looks at the heartbeats and feeds the
+/// hardware watchdog.
+const CHECK_MS: u64 = 1000;
+/// The chip resets by itself when the supervisor stops running, e.g.
+/// while blocking SD card I/O hangs the executor. Longer than any task
+/// deadline, so a slow task is named before the hardware steps in.
+const HARDWARE_TIMEOUT_SECS: u64 = 40;
+
+const _: () = assert!(
+    HARDWARE_TIMEOUT_SECS * 1000 > Task::longest_deadline_ms(),
+    "watchdog: the hardware timeout has to outlast every task deadline",
+);
+
+static HEARTBEATS: Mutex<CriticalSectionRawMutex, RefCell<Heartbeats>> =
+    Mutex::new(RefCell::new(Heartbeats::new()));
+
+/// Call at least every `task.deadline_ms()` while running.
+pub fn check_in(task: Task) {
+    let now = Instant::now().as_millis();
+    HEARTBEATS.lock(|h| h.borrow_mut().check_in(task, now));
+}
+
+/// Call right before waiting for an event that may never come.
+pub fn wait(task: Task) {
+    HEARTBEATS.lock(|h| h.borrow_mut().wait(task));
+}
+
+/// Call after the CPU slept, so the time asleep is not held against
+/// anyone.
+pub fn excuse() {
+    let now = Instant::now().as_millis();
+    HEARTBEATS.lock(|h| h.borrow_mut().excuse(now));
+}
+
+/// Feeds the hardware watchdog while every task keeps its deadline.
+/// Otherwise the hung task is recorded as the crash reason and the chip
+/// is reset.
+#[embassy_executor::task]
+pub async fn watchdog_task(mut wdt: Wdt<TIMG1<'static>>) {
+    wdt.set_timeout(MwdtStage::Stage0, esp_hal::time::Duration::from_secs(HARDWARE_TIMEOUT_SECS));
+    wdt.set_stage_action(MwdtStage::Stage0, MwdtStageAction::ResetSystem);
+    wdt.enable();
+    info!("Watchdog armed, {} s", HARDWARE_TIMEOUT_SECS);
+
+    loop {
+        Timer::after_millis(CHECK_MS).await;
+
+        let now = Instant::now().as_millis();
+        if let Some((task, late)) = HEARTBEATS.lock(|h| h.borrow().overdue(now)) {
+            error!("Watchdog: {} missed its deadline by {} ms", task.name(), late);
+            crash::record_reset(&format!("watchdog: {} hung", task.name()));
+            esp_hal::system::software_reset();
+        }
+        wdt.feed();
+    }
+}
@@ -0,0 +1,206 @@
+use embassy_time::{Duration, Instant};
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+use alloc::string::String;
+
+use crate::button::ButtonEvent;
+use crate::frame::{Frame, WIDTH};
+use crate::menu::{MenuCommand, MenuRequest, MENU_REQ_CH};
+use crate::text::{self, FontSize};
+
+const SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+const SPINNER_STEP_MS: u64 = 150;
+const BAR_WIDTH: u32 = 96;
+const BAR_HEIGHT: u32 = 6;
+
+/// A screen shown over the menu until it is answered or closed.
+#[derive(Clone)]
+pub enum Dialog {
+    /// Yes/No question, `on_yes` is run when confirmed. No is preselected.
+    Confirm {
+        title: &'static str,
+        message: String,
+        on_yes: MenuCommand,
+    },
+    /// Closes by itself after `timeout`, or on any button.
+    Alert {
+        title: &'static str,
+        message: String,
+        timeout: Duration,
+    },
+    /// Stays until the task that opened it closes it, or Select hides
+    /// it while the work carries on. A spinner is shown while `percent`
+    /// is `None`.
+    Progress {
+        title: &'static str,
+        message: String,
+        percent: Option<u8>,
+    },
+}
+
+#[derive(Clone)]
+pub enum DialogMsg {
+    Open(Dialog),
+    /// Update the open progress dialog.
+    Progress(Option<u8>),
+    /// Close the progress dialog, other dialogs are left open.
+    Close,
+}
+
+async fn send(msg: DialogMsg) {
+    MENU_REQ_CH.send(MenuRequest::Dialog(msg)).await;
+}
+
+pub async fn confirm(title: &'static str, message: String, on_yes: MenuCommand) {
+    send(DialogMsg::Open(Dialog::Confirm { title, message, on_yes })).await;
+}
+
+pub async fn alert(title: &'static str, message: String, timeout: Duration) {
+    send(DialogMsg::Open(Dialog::Alert { title, message, timeout })).await;
+}
+
+pub async fn progress(title: &'static str, message: String) {
+    send(DialogMsg::Open(Dialog::Progress { title, message, percent: None })).await;
+}
+
+/// Dropped while the menu task is behind, the next update or the close
+/// makes up for it.
+pub fn set_progress(percent: u8) {
+    let _ = MENU_REQ_CH.try_send(MenuRequest::Dialog(DialogMsg::Progress(Some(percent.min(100)))));
+}
+
+pub async fn close() {
+    send(DialogMsg::Close).await;
+}
+
+/// What a button press did to the open dialog.
+pub enum Outcome {
+    Open,
+    Closed,
+    Confirmed(MenuCommand),
+}
+
+/// The dialog on screen and its state.
+pub struct ActiveDialog {
+    dialog: Dialog,
+    opened: Instant,
+    yes: bool,
+}
+
+impl ActiveDialog {
+    pub fn new(dialog: Dialog) -> Self {
+        Self {
+            dialog,
+            opened: Instant::now(),
+            yes: false,
+        }
+    }
+
+    pub fn set_percent(&mut self, value: Option<u8>) {
+        if let Dialog::Progress { percent, .. } = &mut self.dialog {
+            *percent = value;
+        }
+    }
+
+    pub fn is_progress(&self) -> bool {
+        matches!(self.dialog, Dialog::Progress { .. })
+    }
+
+    /// Alerts that have been up long enough.
+    pub fn expired(&self) -> bool {
+        match &self.dialog {
+            Dialog::Alert { timeout, .. } => self.opened.elapsed() >= *timeout,
+            _ => false,
+        }
+    }
+
+    /// Select hides a progress screen, other buttons leave it up.
+    pub fn handle(&mut self, evt: ButtonEvent) -> Outcome {
+        match (&self.dialog, evt) {
+            (Dialog::Confirm { .. }, ButtonEvent::Up | ButtonEvent::Down) => {
+                self.yes = !self.yes;
+                Outcome::Open
+            }
+            (Dialog::Confirm { on_yes, .. }, ButtonEvent::Select) if self.yes => {
+                Outcome::Confirmed(*on_yes)
+            }
+            (Dialog::Confirm { .. }, ButtonEvent::Select | ButtonEvent::Back) => Outcome::Closed,
+            (Dialog::Confirm { .. }, _) => Outcome::Open,
+            (Dialog::Alert { .. }, _) => Outcome::Closed,
+            (Dialog::Progress { .. }, ButtonEvent::Select) => Outcome::Closed,
+            (Dialog::Progress { .. }, _) => Outcome::Open,
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let on = FontSize::Small.style(BinaryColor::On);
+        let off = FontSize::Small.style(BinaryColor::Off);
+        let width = WIDTH as u32;
+
+        frame.clear_buffer();
+        Rectangle::new(Point::zero(), Size::new(width, frame.height() as u32))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(frame)
+            .ok();
+
+        let (title, message) = match &self.dialog {
+            Dialog::Confirm { title, message, .. }
+            | Dialog::Alert { title, message, .. }
+            | Dialog::Progress { title, message, .. } => (*title, message.as_str()),
+        };
+
+        // title bar
+        Rectangle::new(Point::zero(), Size::new(width, 9))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(frame)
+            .ok();
+        text::draw(frame, &text::ellipsize(title, width - 4, FontSize::Small), 2, 1, off);
+        text::draw(frame, &text::ellipsize(message, width - 4, FontSize::Small), 2, 11, on);
+
+        match &self.dialog {
+            Dialog::Confirm { .. } => {
+                draw_button(frame, "No", 24, !self.yes);
+                draw_button(frame, "Yes", 80, self.yes);
+            }
+            Dialog::Alert { .. } => {}
+            Dialog::Progress { percent: Some(percent), .. } => {
+                let x = ((width - BAR_WIDTH) / 2) as i32;
+                let bar = Rectangle::new(Point::new(x, 21), Size::new(BAR_WIDTH, BAR_HEIGHT));
+                bar.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                    .draw(frame)
+                    .ok();
+                let filled = BAR_WIDTH * (*percent).min(100) as u32 / 100;
+                Rectangle::new(bar.top_left, Size::new(filled, BAR_HEIGHT))
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                    .draw(frame)
+                    .ok();
+            }
+            Dialog::Progress { percent: None, .. } => {
+                let step = self.opened.elapsed().as_millis() / SPINNER_STEP_MS;
+                let spinner = SPINNER[step as usize % SPINNER.len()];
+                text::draw(frame, spinner, (width / 2) as i32 - 2, 21, on);
+            }
+        }
+    }
+}
+
+fn draw_button(frame: &mut Frame, label: &str, x: i32, selected: bool) {
+    let width = text::width(label, FontSize::Small) + 6;
+    let area = Rectangle::new(Point::new(x, 20), Size::new(width, 10));
+
+    if selected {
+        area.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(frame)
+            .ok();
+        text::draw(frame, label, x + 3, 21, FontSize::Small.style(BinaryColor::Off));
+    } else {
+        area.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(frame)
+            .ok();
+        text::draw(frame, label, x + 3, 21, FontSize::Small.style(BinaryColor::On));
+    }
+}
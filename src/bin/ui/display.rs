@@ -0,0 +1,250 @@
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    signal::Signal,
+};
+use embassy_futures::select::{select, Either};
+use embassy_time::{with_timeout, Duration, Instant};
+use display_interface::DisplayError;
+use ssd1306::{command, mode::BasicMode, prelude::*, size::DisplaySizeAsync, I2CDisplayInterface, Ssd1306Async};
+use defmt::info;
+
+use crate::board::{Controller, DisplayConfig};
+use crate::frame::{self, Frame, FrameSink, Panel, Region};
+use crate::health::{self, Health, Subsystem};
+use crate::layout::{self, Content};
+use crate::screen;
+use crate::sh1106::Sh1106;
+use crate::watchdog::{self, Task};
+
+const POWER_POLL_MS: u64 = 250;
+const STATS_SECS: u64 = 60;
+
+/// esp-hal has no I2C DMA on the ESP32-S3, the async driver moves the
+/// bytes from its interrupt handler instead.
+type Bus = I2CInterface<esp_hal::i2c::master::I2c<'static, esp_hal::Async>>;
+
+/// One panel, whichever controller and size the board has.
+pub enum Display {
+    Ssd1306x32(Ssd1306Async<Bus, DisplaySize128x32, BasicMode>),
+    Ssd1306x64(Ssd1306Async<Bus, DisplaySize128x64, BasicMode>),
+    Sh1106(Sh1106<Bus>),
+}
+
+/// Latest frame of the top bar and of the menu. A frame that is not
+/// picked up in time is replaced by the next one.
+pub static TOP_BAR_FRAMES: Signal<CriticalSectionRawMutex, Frame> = Signal::new();
+pub static MENU_FRAMES: Signal<CriticalSectionRawMutex, Frame> = Signal::new();
+
+/// Bring up the panel described by `config` on `i2c`. A panel that does
+/// not answer is recorded as absent.
+pub async fn init(
+    i2c: esp_hal::i2c::master::I2c<'static, esp_hal::Async>,
+    config: DisplayConfig,
+    subsystem: Subsystem,
+) -> Option<Display> {
+    let bus = I2CDisplayInterface::new(i2c);
+    // the first frame rewrites all of GDDRAM, no need to clear here
+    let result = match (config.controller, config.height) {
+        (Controller::Ssd1306, 64) => {
+            let mut display = Ssd1306Async::new(bus, DisplaySize128x64, DisplayRotation::Rotate0);
+            display.init_with_addr_mode(command::AddrMode::Horizontal).await.map(|()| Display::Ssd1306x64(display))
+        }
+        (Controller::Ssd1306, _) => {
+            let mut display = Ssd1306Async::new(bus, DisplaySize128x32, DisplayRotation::Rotate0);
+            display.init_with_addr_mode(command::AddrMode::Horizontal).await.map(|()| Display::Ssd1306x32(display))
+        }
+        (Controller::Sh1106, _) => {
+            let mut display = Sh1106::new(bus);
+            display.init().await.map(|()| Display::Sh1106(display))
+        }
+    };
+
+    match result {
+        Ok(display) => {
+            health::set(subsystem, Health::Ok);
+            Some(display)
+        }
+        Err(_) => {
+            health::set(subsystem, Health::Absent);
+            None
+        }
+    }
+}
+
+impl Display {
+    /// Rows of the panel.
+    pub fn height(&self) -> usize {
+        match self {
+            Display::Ssd1306x32(_) => 32,
+            Display::Ssd1306x64(_) | Display::Sh1106(_) => 64,
+        }
+    }
+}
+
+impl FrameSink for Display {
+    type Error = DisplayError;
+
+    async fn set_power(&mut self, on: bool) -> Result<(), DisplayError> {
+        match self {
+            Display::Ssd1306x32(display) => display.set_power(on).await,
+            Display::Ssd1306x64(display) => display.set_power(on).await,
+            Display::Sh1106(display) => display.set_power(on).await,
+        }
+    }
+
+    async fn set_level(&mut self, percent: u8) -> Result<(), DisplayError> {
+        match self {
+            Display::Ssd1306x32(display) => display.set_level(percent).await,
+            Display::Ssd1306x64(display) => display.set_level(percent).await,
+            Display::Sh1106(display) => display.set_level(percent).await,
+        }
+    }
+
+    async fn write_region(&mut self, region: Region, data: &[u8]) -> Result<(), DisplayError> {
+        match self {
+            Display::Ssd1306x32(display) => display.write_region(region, data).await,
+            Display::Ssd1306x64(display) => display.write_region(region, data).await,
+            Display::Sh1106(display) => display.write_region(region, data).await,
+        }
+    }
+}
+
+impl<SIZE: DisplaySizeAsync> FrameSink for Ssd1306Async<Bus, SIZE, BasicMode> {
+    type Error = DisplayError;
+
+    async fn set_power(&mut self, on: bool) -> Result<(), DisplayError> {
+        self.set_display_on(on).await
+    }
+
+    async fn set_level(&mut self, percent: u8) -> Result<(), DisplayError> {
+        self.set_brightness(display_brightness(percent)).await
+    }
+
+    async fn write_region(&mut self, region: Region, data: &[u8]) -> Result<(), DisplayError> {
+        self.set_draw_area(
+            (region.start_col, region.first_page * 8),
+            (region.end_col, (region.last_page + 1) * 8),
+        )
+        .await?;
+        self.draw(data).await
+    }
+}
+
+/// SSD1306 contrast for a brightness in percent.
+pub fn display_brightness(percent: u8) -> Brightness {
+    let contrast = (percent.min(100) as u16 * 255 / 100) as u8;
+    // the lowest precharge lets the panel go a bit dimmer at night
+    let precharge = if percent <= 10 { 0x1 } else { 0x2 };
+    Brightness::custom(precharge, contrast)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PanelState {
+    Unknown,
+    Off,
+    On(u8),
+}
+
+/// Bring `sink` in line with the screen state and brightness.
+/// `panel` remembers what was last sent to it. Returns false while the
+/// panel is off.
+pub async fn sync_power<S: FrameSink>(sink: &mut S, panel: &mut PanelState) -> bool {
+    let target = match screen::panel_brightness() {
+        Some(percent) => PanelState::On(percent),
+        None => PanelState::Off,
+    };
+    if target == *panel {
+        return target != PanelState::Off;
+    }
+
+    match target {
+        PanelState::Off => {
+            sink.set_power(false).await.ok();
+        }
+        PanelState::On(percent) => {
+            if !matches!(*panel, PanelState::On(_)) {
+                sink.set_power(true).await.ok();
+            }
+            sink.set_level(percent).await.ok();
+        }
+        PanelState::Unknown => {}
+    }
+    *panel = target;
+
+    target != PanelState::Off
+}
+
+/// Next frame of the parts in `content`, and the panel row it goes to.
+async fn next_part(content: Content) -> (Frame, usize) {
+    match content {
+        Content::TopBar => (TOP_BAR_FRAMES.wait().await, 0),
+        Content::Menu => (MENU_FRAMES.wait().await, 0),
+        Content::Both => match select(TOP_BAR_FRAMES.wait(), MENU_FRAMES.wait()).await {
+            Either::First(frame) => (frame, 0),
+            Either::Second(frame) => (frame, layout::get().menu_y()),
+        },
+    }
+}
+
+/// Owns one panel and pushes the frames composed by the UI tasks to it,
+/// so a slow bus never holds up input handling. On a shared panel the
+/// top bar and menu frames are put together first.
+#[embassy_executor::task(pool_size = 2)]
+pub async fn display_task(
+    mut display: Display,
+    content: Content,
+    name: &'static str,
+    task: Task,
+    i2c_khz: u32,
+) {
+    let height = display.height();
+    let mut panel = Panel::new(height);
+    let mut composed = Frame::new(height);
+    let mut power = PanelState::Unknown;
+    let mut pending = false;
+    let mut last_stats = Instant::now();
+    let mut flush_us: u64 = 0;
+    let mut flushes: u32 = 0;
+
+    loop {
+        watchdog::check_in(task);
+        // wake up now and then to follow night mode and the screen timeout
+        if let Ok((part, y)) = with_timeout(Duration::from_millis(POWER_POLL_MS), next_part(content)).await {
+            composed.blit(&part, y / 8);
+            pending = true;
+        }
+
+        // keep the frame for when the panel comes back on
+        if !sync_power(&mut display, &mut power).await {
+            continue;
+        }
+
+        if pending {
+            pending = false;
+            let started = Instant::now();
+            if panel.flush(&mut display, &composed).await.is_err() {
+                info!("{} display: flush failed", name);
+            }
+            flush_us += started.elapsed().as_micros();
+            flushes += 1;
+        }
+
+        // what a frame costs now, against a full flush at the old clock
+        if last_stats.elapsed().as_secs() >= STATS_SECS {
+            let full = frame::full_flush_bus_bytes(height / 8);
+            info!(
+                "{} display: {} bus bytes/frame, {} us/frame at {} kHz; before: {} bytes, {} us at {} kHz",
+                name,
+                panel.take_average_bytes(),
+                flush_us.checked_div(flushes as u64).unwrap_or(0),
+                i2c_khz,
+                full,
+                frame::bus_time_us(full, frame::DEFAULT_I2C_KHZ),
+                frame::DEFAULT_I2C_KHZ
+            );
+            flush_us = 0;
+            flushes = 0;
+            last_stats = Instant::now();
+        }
+    }
+}
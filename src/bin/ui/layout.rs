@@ -0,0 +1,17 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+
+pub use bitband::layout::{Content, Layout};
+
+static LAYOUT: Mutex<CriticalSectionRawMutex, Cell<Layout>> =
+    Mutex::new(Cell::new(Layout::Split { top: 32, bottom: 32 }));
+
+/// Called once by main, before the UI tasks start.
+pub fn set(layout: Layout) {
+    LAYOUT.lock(|l| l.set(layout));
+}
+
+pub fn get() -> Layout {
+    LAYOUT.lock(|l| l.get())
+}
@@ -0,0 +1,143 @@
+use embassy_time::Instant;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+use crate::frame::Frame;
+use crate::text::{self, FontSize};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MarqueeMode {
+    /// Scroll to the end, pause, scroll back.
+    Bounce,
+    /// Keep scrolling left, the start follows the end after a gap.
+    Wrap,
+}
+
+/// Scrolls text that is wider than its field. The position only
+/// depends on the time since the text was first shown, so it moves at
+/// the same speed however often it is redrawn.
+#[derive(Copy, Clone, Debug)]
+pub struct Marquee {
+    pub mode: MarqueeMode,
+    pub speed_px_per_sec: u32,
+    pub start_pause_ms: u32,
+    /// Pause at the far end, only used by `Bounce`.
+    pub end_pause_ms: u32,
+    /// Space between the end and the repeated start, only used by `Wrap`.
+    pub gap_px: u32,
+}
+
+impl Marquee {
+    pub const fn new(mode: MarqueeMode) -> Self {
+        Self {
+            mode,
+            speed_px_per_sec: 20,
+            start_pause_ms: 1000,
+            end_pause_ms: 1000,
+            gap_px: 16,
+        }
+    }
+
+    pub const fn with_speed(mut self, px_per_sec: u32) -> Self {
+        self.speed_px_per_sec = px_per_sec;
+        self
+    }
+
+    pub const fn with_pauses(mut self, start_ms: u32, end_ms: u32) -> Self {
+        self.start_pause_ms = start_ms;
+        self.end_pause_ms = end_ms;
+        self
+    }
+
+    /// True if text this wide has to move to fit `view_width`.
+    pub fn scrolls(content_width: u32, view_width: u32) -> bool {
+        content_width > view_width
+    }
+
+    fn travel_ms(&self, px: u32) -> u32 {
+        px * 1000 / self.speed_px_per_sec.max(1)
+    }
+
+    fn travelled(&self, ms: u32) -> u32 {
+        ms * self.speed_px_per_sec / 1000
+    }
+
+    /// How many pixels the text is shifted left `elapsed_ms` after it
+    /// was first shown.
+    pub fn offset(&self, content_width: u32, view_width: u32, elapsed_ms: u32) -> u32 {
+        if !Self::scrolls(content_width, view_width) {
+            return 0;
+        }
+
+        match self.mode {
+            MarqueeMode::Bounce => {
+                let overflow = content_width - view_width;
+                let scroll = self.travel_ms(overflow);
+                let cycle = self.start_pause_ms + scroll + self.end_pause_ms + scroll;
+                let mut t = elapsed_ms % cycle.max(1);
+
+                if t < self.start_pause_ms {
+                    return 0;
+                }
+                t -= self.start_pause_ms;
+                if t < scroll {
+                    return self.travelled(t).min(overflow);
+                }
+                t -= scroll;
+                if t < self.end_pause_ms {
+                    return overflow;
+                }
+                t -= self.end_pause_ms;
+                overflow.saturating_sub(self.travelled(t))
+            }
+            MarqueeMode::Wrap => {
+                let distance = content_width + self.gap_px;
+                let cycle = self.start_pause_ms + self.travel_ms(distance);
+                let t = elapsed_ms % cycle.max(1);
+
+                if t < self.start_pause_ms {
+                    0
+                } else {
+                    self.travelled(t - self.start_pause_ms).min(distance) % distance
+                }
+            }
+        }
+    }
+
+    /// Draw `label` into `area`, scrolled for the time since `since`.
+    /// Nothing is drawn outside `area`.
+    pub fn draw(
+        &self,
+        frame: &mut Frame,
+        label: &str,
+        area: Rectangle,
+        since: Instant,
+        size: FontSize,
+        style: MonoTextStyle<'_, BinaryColor>,
+    ) {
+        let content = text::width(label, size);
+        let elapsed = since.elapsed().as_millis() as u32;
+        let offset = self.offset(content, area.size.width, elapsed) as i32;
+        let label = text::sanitize(label);
+
+        let mut clipped = frame.clipped(&area);
+        let x = area.top_left.x - offset;
+        let y = area.top_left.y;
+
+        Text::with_baseline(&label, Point::new(x, y), style, Baseline::Top)
+            .draw(&mut clipped)
+            .ok();
+
+        if self.mode == MarqueeMode::Wrap && offset > 0 {
+            let x = x + (content + self.gap_px) as i32;
+            Text::with_baseline(&label, Point::new(x, y), style, Baseline::Top)
+                .draw(&mut clipped)
+                .ok();
+        }
+    }
+}
@@ -6,61 +6,84 @@ use embassy_sync::{
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::primitives::PrimitiveStyleBuilder;
 use embedded_graphics::primitives::Rectangle;
-use esp_radio::wifi::ScanConfig;
+use esp_radio::wifi::AuthMethod;
+use esp_radio::wifi::{AccessPointInfo, ScanConfig, WifiError};
 use esp_radio::wifi::WifiController;
 use ssd1306::{prelude::*, mode::BufferedGraphicsMode};
 use esp_hal::i2c;
 use esp_hal::peripherals;
 use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306, command};
 use embedded_graphics::{
-    mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
     prelude::*,
     text::{Baseline, Text}
 };
+use embassy_time::{Duration, Instant, Timer};
+use smart_leds::colors;
 use defmt::info;
 
 use alloc::vec::Vec;
 use alloc::boxed::Box;
-use alloc::vec;
-
-use core::sync::atomic::{AtomicPtr, Ordering};
+use alloc::format;
+use alloc::string::String;
 
+use crate::app_state::{self, RadioState};
+use crate::battery_policy::{self, BatteryLevel};
+use crate::board::BOARD;
+use crate::bus::TopicSubscriber;
 use crate::button::*;
-use crate::top_bar::{TopBarMode, TOP_BAR_CH, draw_text_at};
+use crate::crash;
+use crate::deauth;
+use crate::led::{self, LedSource, Pattern, Priority};
+use crate::marquee::{Marquee, MarqueeMode};
+use crate::power;
+use crate::rogue_ap::{self, Auth, Sighting};
+use crate::clock;
+use crate::screen;
+use crate::settings;
+use crate::storage;
+use crate::dialog::{self, ActiveDialog, Dialog, DialogMsg, Outcome};
+use crate::display::MENU_FRAMES;
+use crate::health::{self, Subsystem};
+use crate::frame::{Frame, WIDTH};
+use crate::layout;
+use crate::text::{self, FontSize};
+use crate::top_bar::{self, TopBarMode};
+use crate::watchdog::{self, Task};
+use crate::widget::{BarLayout, Slot, WidgetKind};
+
+use bitband::menu::{breadcrumb, next_event, scrollbar_thumb, MenuEvent};
+pub use bitband::menu::{
+    Choice, Effect, Menu, MenuAction, MenuCommand, MenuItem, MenuRequest, MenuState, Slider, Toggle,
+    WifiApInfo,
+};
 
 const TITLE_HEIGHT: i32 = 8;
 const LINE_HEIGHT: i32 = 8;
-const DISPLAY_HEIGHT: i32 = 32;
-const VISIBLE_LINES: usize =
-    ((DISPLAY_HEIGHT - TITLE_HEIGHT) / LINE_HEIGHT) as usize;
-
-pub enum MenuAction {
-    Enter(&'static Menu),
-    Trigger(MenuCommand),
-    WifiAp(&'static WifiApInfo),
-}
-
-#[derive(Copy, Clone)]
-pub enum MenuCommand {
-    BleScan,
-    WifiScan,
-    WifiDeauthSelected,
-    WifiClearSelected,
-    ToggleBluetooth,
-    Reboot,
-    EnterDynamic(&'static Menu),
-}
-
-pub struct MenuItem {
-    pub label: &'static str,
-    pub action: MenuAction,
-}
-
-pub struct Menu {
-    pub title: &'static str,
-    pub items: &'static [MenuItem],
-}
+const SCROLLBAR_WIDTH: u32 = 2;
+/// Rows leave this much room for the scrollbar when there is one.
+const SCROLLBAR_SPACE: u32 = SCROLLBAR_WIDTH + 1;
+/// Space between a label and its value.
+const VALUE_GAP: u32 = 4;
+/// Redraw period while the selected label scrolls, one pixel per frame.
+const MARQUEE_FRAME_MS: u64 = 50;
+/// Redraw and dialog update period while a dialog is open.
+const DIALOG_REFRESH_MS: u64 = 100;
+const ALERT_SECS: u64 = 2;
+/// Every file a factory reset removes.
+const FACTORY_RESET_FILES: [&str; 6] = [
+    settings::SETTINGS_FILE,
+    crash::LOG_FILE,
+    deauth::CONFIG_FILE,
+    deauth::LOG_FILE,
+    rogue_ap::ALLOWLIST_FILE,
+    rogue_ap::LOG_FILE,
+];
+/// 2.4 GHz channels scanned from the menu.
+const SCAN_CHANNELS: u8 = 13;
+/// Redraw period for menus showing live values.
+const LIVE_REFRESH_MS: u64 = 1000;
+const MENU_MARQUEE: Marquee = Marquee::new(MarqueeMode::Bounce).with_pauses(1000, 800);
 
 pub static WIFI_ACTIONS_MENU: Menu = Menu {
     title: "WiFi Actions",
@@ -70,11 +93,6 @@ pub static WIFI_ACTIONS_MENU: Menu = Menu {
             // action: MenuAction::Trigger(MenuCommand::WifiConnectSelected),
             action: MenuAction::Trigger(MenuCommand::Reboot),
         },
-        MenuItem {
-            label: "Deauth Test",
-            // action: MenuAction::Trigger(MenuCommand::WifiDeauthSelected),
-            action: MenuAction::Trigger(MenuCommand::WifiDeauthSelected),
-        },
         MenuItem {
             label: "Clear Selection",
             // action: MenuAction::Trigger(MenuCommand::WifiClearSelected),
@@ -83,12 +101,249 @@ pub static WIFI_ACTIONS_MENU: Menu = Menu {
     ],
 };
 
+const SCREEN_TIMEOUTS: [u16; 5] = [0, 15, 30, 60, 120];
+const WIDGET_SLOTS: [Option<Slot>; 4] = [None, Some(Slot::Left), Some(Slot::Center), Some(Slot::Right)];
+
 pub static SETTINGS_MENU: Menu = Menu {
     title: "Settings",
     items: &[
         MenuItem {
             label: "Bluetooth",
-            action: MenuAction::Trigger(MenuCommand::ToggleBluetooth),
+            action: MenuAction::Toggle(Toggle {
+                get: || app_state::get().bluetooth,
+                set: |on| {
+                    if on && !health::ok(Subsystem::Bluetooth) {
+                        return;
+                    }
+                    app_state::set_bluetooth(on);
+                    info!("Bluetooth {}", if on { "on" } else { "off" });
+                },
+            }),
+        },
+        MenuItem {
+            label: "Brightness",
+            action: MenuAction::Slider(Slider {
+                min: 5,
+                max: 100,
+                step: 5,
+                unit: "%",
+                get: || settings::get().brightness as i32,
+                set: |v| settings::update(|s| s.brightness = v as u8),
+            }),
+        },
+        MenuItem {
+            label: "Night Mode",
+            action: MenuAction::Toggle(Toggle {
+                get: || settings::get().night_mode,
+                set: |on| settings::update(|s| s.night_mode = on),
+            }),
+        },
+        MenuItem {
+            label: "Night Start",
+            action: MenuAction::Slider(Slider {
+                min: 0,
+                max: 23,
+                step: 1,
+                unit: ":00",
+                get: || settings::get().night_start_hour as i32,
+                set: |v| settings::update(|s| s.night_start_hour = v as u8),
+            }),
+        },
+        MenuItem {
+            label: "Night End",
+            action: MenuAction::Slider(Slider {
+                min: 0,
+                max: 23,
+                step: 1,
+                unit: ":00",
+                get: || settings::get().night_end_hour as i32,
+                set: |v| settings::update(|s| s.night_end_hour = v as u8),
+            }),
+        },
+        MenuItem {
+            label: "Time",
+            action: MenuAction::Value(|| {
+                let (hh, mm) = clock::now_hhmm();
+                format!("{:02}:{:02}", hh, mm)
+            }),
+        },
+        MenuItem {
+            label: "Clock +1h",
+            action: MenuAction::Trigger(MenuCommand::ClockHourUp),
+        },
+        MenuItem {
+            label: "Clock +5min",
+            action: MenuAction::Trigger(MenuCommand::ClockMinuteUp),
+        },
+        MenuItem {
+            label: "Screen Timeout",
+            action: MenuAction::Choice(Choice {
+                options: &["never", "15s", "30s", "60s", "2min"],
+                get: || {
+                    let secs = settings::get().screen_timeout_secs;
+                    SCREEN_TIMEOUTS.iter().position(|&t| t == secs).unwrap_or(2)
+                },
+                set: |i| settings::update(|s| s.screen_timeout_secs = SCREEN_TIMEOUTS[i]),
+            }),
+        },
+        MenuItem {
+            label: "Top Bar",
+            action: MenuAction::Enter(&TOP_BAR_MENU),
+        },
+        MenuItem {
+            label: "Battery",
+            action: MenuAction::Enter(&BATTERY_MENU),
+        },
+    ],
+};
+
+/// Charge levels of the battery policy.
+pub static BATTERY_MENU: Menu = Menu {
+    title: "Battery",
+    items: &[
+        MenuItem {
+            label: "Warn At",
+            action: MenuAction::Slider(Slider {
+                min: 5,
+                max: 50,
+                step: 5,
+                unit: "%",
+                get: || settings::get().battery_warn_percent as i32,
+                set: |v| settings::update(|s| s.set_battery_threshold(BatteryLevel::Low, v as u8)),
+            }),
+        },
+        MenuItem {
+            label: "Radios Off At",
+            action: MenuAction::Slider(Slider {
+                min: 0,
+                max: 30,
+                step: 1,
+                unit: "%",
+                get: || settings::get().battery_shed_percent as i32,
+                set: |v| settings::update(|s| s.set_battery_threshold(BatteryLevel::Shedding, v as u8)),
+            }),
+        },
+        MenuItem {
+            label: "Shut Down At",
+            action: MenuAction::Slider(Slider {
+                min: 0,
+                max: 10,
+                step: 1,
+                unit: "%",
+                get: || settings::get().battery_critical_percent as i32,
+                set: |v| settings::update(|s| s.set_battery_threshold(BatteryLevel::Critical, v as u8)),
+            }),
+        },
+    ],
+};
+
+fn widget_slot(kind: WidgetKind) -> usize {
+    let slot = settings::get().top_bar.slot_of(kind);
+    WIDGET_SLOTS.iter().position(|&s| s == slot).unwrap_or(0)
+}
+
+fn set_widget_slot(kind: WidgetKind, index: usize) {
+    settings::update(|s| match WIDGET_SLOTS[index] {
+        Some(slot) => s.top_bar.place(kind, slot),
+        None => s.top_bar.remove(kind),
+    });
+}
+
+const SLOT_OPTIONS: &[&str] = &["off", "left", "center", "right"];
+
+/// Moving a widget to another slot also puts it last in that slot,
+/// which is how widgets are reordered.
+pub static TOP_BAR_MENU: Menu = Menu {
+    title: "Top Bar",
+    items: &[
+        MenuItem {
+            label: "Battery",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Battery),
+                set: |i| set_widget_slot(WidgetKind::Battery, i),
+            }),
+        },
+        MenuItem {
+            label: "Clock",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Clock),
+                set: |i| set_widget_slot(WidgetKind::Clock, i),
+            }),
+        },
+        MenuItem {
+            label: "WiFi",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Wifi),
+                set: |i| set_widget_slot(WidgetKind::Wifi, i),
+            }),
+        },
+        MenuItem {
+            label: "Bluetooth",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Bluetooth),
+                set: |i| set_widget_slot(WidgetKind::Bluetooth, i),
+            }),
+        },
+        MenuItem {
+            label: "SD Card",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Sd),
+                set: |i| set_widget_slot(WidgetKind::Sd, i),
+            }),
+        },
+        MenuItem {
+            label: "Notifications",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Notifications),
+                set: |i| set_widget_slot(WidgetKind::Notifications, i),
+            }),
+        },
+        MenuItem {
+            label: "Free Heap",
+            action: MenuAction::Choice(Choice {
+                options: SLOT_OPTIONS,
+                get: || widget_slot(WidgetKind::Heap),
+                set: |i| set_widget_slot(WidgetKind::Heap, i),
+            }),
+        },
+        MenuItem {
+            label: "Reset Layout",
+            action: MenuAction::Trigger(MenuCommand::ResetTopBar),
+        },
+    ],
+};
+
+pub static SECURITY_MENU: Menu = Menu {
+    title: "Security",
+    items: &[
+        MenuItem {
+            label: "Rogue AP Monitor",
+            action: MenuAction::Toggle(Toggle {
+                get: rogue_ap::monitor_enabled,
+                set: |on| {
+                    // both monitors listen through the WiFi radio
+                    if on != rogue_ap::monitor_enabled() && (!on || health::ok(Subsystem::Wifi)) {
+                        rogue_ap::toggle_monitor();
+                    }
+                },
+            }),
+        },
+        MenuItem {
+            label: "Deauth Monitor",
+            action: MenuAction::Toggle(Toggle {
+                get: deauth::monitor_enabled,
+                set: |on| {
+                    if on != deauth::monitor_enabled() && (!on || health::ok(Subsystem::Wifi)) {
+                        deauth::toggle_monitor();
+                    }
+                },
+            }),
         },
     ],
 };
@@ -107,6 +362,88 @@ pub static RADIO_MENU: Menu = Menu {
     ],
 };
 
+pub static FILES_MENU: Menu = Menu {
+    title: "Files",
+    items: &[
+        MenuItem {
+            label: "Rogue AP Log",
+            action: MenuAction::Trigger(MenuCommand::DeleteFile(rogue_ap::LOG_FILE)),
+        },
+        MenuItem {
+            label: "Deauth Log",
+            action: MenuAction::Trigger(MenuCommand::DeleteFile(deauth::LOG_FILE)),
+        },
+        MenuItem {
+            label: "AP Allowlist",
+            action: MenuAction::Trigger(MenuCommand::DeleteFile(rogue_ap::ALLOWLIST_FILE)),
+        },
+        MenuItem {
+            label: "Crash Log",
+            action: MenuAction::Trigger(MenuCommand::DeleteFile(crash::LOG_FILE)),
+        },
+    ],
+};
+
+pub static HARDWARE_MENU: Menu = Menu {
+    title: "Hardware",
+    items: &[
+        MenuItem {
+            label: "Board",
+            action: MenuAction::Value(|| String::from(BOARD.name)),
+        },
+        MenuItem {
+            label: "Top Display",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::TopDisplay).describe())),
+        },
+        MenuItem {
+            label: "Bottom Display",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::BottomDisplay).describe())),
+        },
+        MenuItem {
+            label: "WiFi",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::Wifi).describe())),
+        },
+        MenuItem {
+            label: "Bluetooth",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::Bluetooth).describe())),
+        },
+        MenuItem {
+            label: "LED",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::Led).describe())),
+        },
+        MenuItem {
+            label: "SD Card",
+            action: MenuAction::Value(|| String::from(health::get(Subsystem::SdCard).describe())),
+        },
+        MenuItem {
+            label: "Last Boot",
+            action: MenuAction::Value(|| String::from(power::boot_reason().describe())),
+        },
+    ],
+};
+
+pub static SYSTEM_MENU: Menu = Menu {
+    title: "System",
+    items: &[
+        MenuItem {
+            label: "Hardware",
+            action: MenuAction::Enter(&HARDWARE_MENU),
+        },
+        MenuItem {
+            label: "Delete Files",
+            action: MenuAction::Enter(&FILES_MENU),
+        },
+        MenuItem {
+            label: "Factory Reset",
+            action: MenuAction::Trigger(MenuCommand::FactoryReset),
+        },
+        MenuItem {
+            label: "Power Off",
+            action: MenuAction::Trigger(MenuCommand::PowerOff),
+        },
+    ],
+};
+
 pub static ROOT_MENU: Menu = Menu {
     title: "Main Menu",
     items: &[
@@ -122,10 +459,18 @@ pub static ROOT_MENU: Menu = Menu {
             label: "Settings",
             action: MenuAction::Enter(&SETTINGS_MENU),
         },
+        MenuItem {
+            label: "Security",
+            action: MenuAction::Enter(&SECURITY_MENU),
+        },
         MenuItem {
             label: "Radio Test",
             action: MenuAction::Enter(&RADIO_MENU),
         },
+        MenuItem {
+            label: "System",
+            action: MenuAction::Enter(&SYSTEM_MENU),
+        },
         MenuItem {
             label: "Reboot",
             action: MenuAction::Trigger(MenuCommand::Reboot),
@@ -133,187 +478,222 @@ pub static ROOT_MENU: Menu = Menu {
     ],
 };
 
-const MENU_DEPTH_MAX: usize = 4;
-
-pub struct MenuState {
-    pub stack: [&'static Menu; MENU_DEPTH_MAX],
-    pub depth: usize,
-    pub selected: usize,
-    pub scroll: usize,
+/// Commands for radio_task. Sent with `send().await` so none are lost
+/// while it is busy.
+pub static MENU_CMD_CH: Channel<CriticalSectionRawMutex, MenuCommand, 4> = Channel::new();
+/// Scan requests for wifi_scan_task, from the menu and the rogue AP
+/// monitor.
+pub static WIFI_SCAN_CH: Channel<CriticalSectionRawMutex, WifiScanRequest, 2> = Channel::new();
+
+/// Menus and dialogs for menu_task. Sent with `send().await`, a lost
+/// close would leave a progress dialog up for good.
+pub static MENU_REQ_CH: Channel<CriticalSectionRawMutex, MenuRequest<DialogMsg>, 8> = Channel::new();
+
+#[derive(Copy, Clone, Debug)]
+pub enum WifiScanRequest {
+    Menu,
+    RogueMonitor,
 }
 
-impl MenuState {
-    pub fn new(root: &'static Menu) -> Self {
-        let mut stack = [root; MENU_DEPTH_MAX];
-        stack[0] = root;
-        Self {
-            stack,
-            depth: 1,
-            selected: 0,
-            scroll: 0,
-        }
-    }
-
-    pub fn current(&self) -> &'static Menu {
-        self.stack[self.depth - 1]
-    }
-
-    pub fn enter(&mut self, menu: &'static Menu) {
-        if self.depth < MENU_DEPTH_MAX {
-            self.stack[self.depth] = menu;
-            self.depth += 1;
-            self.selected = 0;
-            self.scroll = 0;
-        }
-    }
-
-    pub fn back(&mut self) {
-        if self.depth > 1 {
-            self.depth -= 1;
-            self.selected = 0;
-            self.scroll = 0;
-        }
-    }
-}
-
-pub enum MenuMsg {
-    PushMenu(&'static Menu),
-    UpdateTopBar(TopBarMode),
-}
-
-pub static MENU_MSG_CH: Channel<
-    CriticalSectionRawMutex,
-    MenuMsg,
-    4,
-> = Channel::new();
-
-pub static MENU_CMD_CH: Channel<
-    CriticalSectionRawMutex,
-    MenuCommand,
-    4,
-> = Channel::new();
-
-pub static WIFI_SCAN_CH: Channel<CriticalSectionRawMutex, (), 1> = Channel::new();
-
-pub struct WifiApInfo {
-    pub ssid: &'static str,
-    pub rssi: i8,
-    pub channel: u8,
-    // pub auth: AuthMethod,
-}
-
-static SELECTED_WIFI_AP: AtomicPtr<WifiApInfo> =
-    AtomicPtr::new(core::ptr::null_mut());
-
-type Display = ssd1306::Ssd1306<ssd1306::prelude::I2CInterface<esp_hal::i2c::master::I2c<'static, esp_hal::Blocking>>, DisplaySize128x32, BufferedGraphicsMode<DisplaySize128x32>>;
-
+/// `buttons` is taken by main before button_task is spawned.
 #[embassy_executor::task]
-pub async fn menu_task(mut display: Display) {
-    let normal = MonoTextStyleBuilder::new()
-        .font(&FONT_6X10)
-        .text_color(BinaryColor::On)
-        .build();
-
-    let inverted = MonoTextStyleBuilder::new()
-        .font(&FONT_6X10)
-        .text_color(BinaryColor::Off)
-        .build();
+pub async fn menu_task(mut buttons: TopicSubscriber<ButtonEvent, 4>) {
+    let normal = FontSize::Normal.style(BinaryColor::On);
+    let inverted = FontSize::Normal.style(BinaryColor::Off);
 
     let mut state = MenuState::new(&ROOT_MENU);
+    let mut dialog: Option<ActiveDialog> = None;
+    let mut frame = Frame::new(layout::get().menu_height());
 
-    render_menu(&mut display, &state, normal, inverted, VISIBLE_LINES);
+    let mut selected_since = Instant::now();
+    let mut animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
 
     loop {
-        let evt = BUTTON_CH.receive().await;
-
-        match evt {
-            ButtonEvent::Up => {
-                let len = state.current().items.len();
-                if len == 0 {
-                    state.selected = 0;
-                } else if state.selected > 0 {
-                    state.selected -= 1;
-                } else {
-                    state.selected = len - 1;
-                }
+        watchdog::check_in(Task::Menu);
+        // keep a long selected label moving, live values current and
+        // dialogs updated without waiting for a press
+        let live = state.current().items.iter().any(|item| matches!(item.action, MenuAction::Value(_)));
+        let refresh = match (dialog.is_some(), animating, live) {
+            _ if screen::panel_brightness().is_none() => None,
+            (true, _, _) => Some(DIALOG_REFRESH_MS),
+            (false, true, _) => Some(MARQUEE_FRAME_MS),
+            (false, false, true) => Some(LIVE_REFRESH_MS),
+            (false, false, false) => None,
+        };
+
+        if refresh.is_none() {
+            watchdog::wait(Task::Menu);
+        }
+        let tick = async {
+            match refresh {
+                Some(ms) => Timer::after_millis(ms).await,
+                None => core::future::pending().await,
             }
-            ButtonEvent::Down => {
-                let len = state.current().items.len();
-                if len == 0 {
-                    state.selected = 0;
-                } else if state.selected + 1 < len {
-                    state.selected += 1;
-                } else {
-                    state.selected = 0;
+        };
+        let evt = match next_event(MENU_REQ_CH.receive(), buttons.next(), tick).await {
+            MenuEvent::Tick => {
+                if dialog.as_ref().is_some_and(ActiveDialog::expired) {
+                    dialog = None;
                 }
+                animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
+                continue;
             }
-            ButtonEvent::Select => {
-                match state.current().items[state.selected].action {
-                    MenuAction::WifiAp(ap) => {
-                        set_selected_ap(ap);
-                    }
-                    MenuAction::Enter(sub) => state.enter(sub),
-                    MenuAction::Trigger(cmd) => match cmd {
-                        MenuCommand::WifiScan => {
-                            WIFI_SCAN_CH.send(()).await;
+            MenuEvent::Request(MenuRequest::PushMenu(menu)) => {
+                state.enter(menu);
+                selected_since = Instant::now();
+                animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
+                continue;
+            }
+            MenuEvent::Request(MenuRequest::Dialog(msg)) => {
+                match msg {
+                    DialogMsg::Open(opened) => dialog = Some(ActiveDialog::new(opened)),
+                    DialogMsg::Progress(percent) => {
+                        if let Some(active) = dialog.as_mut() {
+                            active.set_percent(percent);
                         }
-                        MenuCommand::EnterDynamic(menu) => state.enter(menu),
-                        _ => {
-                            MENU_CMD_CH.send(cmd).await;
+                    }
+                    DialogMsg::Close => {
+                        if dialog.as_ref().is_some_and(ActiveDialog::is_progress) {
+                            dialog = None;
                         }
-                    },
+                    }
                 }
+                animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
+                continue;
             }
-            ButtonEvent::Back => state.back(),
-            _ => {}
-        }
-
-        // scrolling logic
-        normalize_menu_state(&mut state);
+            MenuEvent::Button(evt) => evt,
+        };
+        let before = (state.current() as *const Menu, state.selected);
 
-        if let Ok(msg) = MENU_MSG_CH.try_receive() {
-            match msg {
-                MenuMsg::PushMenu(menu) => state.enter(menu),
-                MenuMsg::UpdateTopBar(info) => {
-                    TOP_BAR_CH.send(info).await;
+        // the press that turns the screen back on is not acted upon
+        if screen::wake() {
+            continue;
+        }
+        top_bar::clear_notifications();
+
+        // an open dialog takes all input
+        if let Some(active) = dialog.as_mut() {
+            match active.handle(evt) {
+                Outcome::Open => {}
+                Outcome::Closed => dialog = None,
+                Outcome::Confirmed(cmd) => {
+                    dialog = None;
+                    MENU_CMD_CH.send(cmd).await;
                 }
             }
+            animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
+            continue;
+        }
+
+        match state.press(evt, visible_lines()) {
+            None => {}
+            Some(Effect::SelectAp(ap)) => app_state::select_ap(Some(ap)),
+            Some(Effect::Command(MenuCommand::WifiScan)) => {
+                dialog = Some(ActiveDialog::new(Dialog::Progress {
+                    title: "WiFi Scan",
+                    message: String::from("Scanning..."),
+                    percent: None,
+                }));
+                WIFI_SCAN_CH.send(WifiScanRequest::Menu).await;
+            }
+            Some(Effect::Command(cmd)) => match cmd.confirmation() {
+                Some((title, message)) => {
+                    dialog = Some(ActiveDialog::new(Dialog::Confirm {
+                        title,
+                        message,
+                        on_yes: cmd,
+                    }));
+                }
+                None => MENU_CMD_CH.send(cmd).await,
+            },
         }
 
         if let Some(MenuItem { action: MenuAction::WifiAp(ap), .. }) =
             state.current().items.get(state.selected)
         {
-            let _ = MENU_MSG_CH.try_send(
-                MenuMsg::UpdateTopBar(
-                    TopBarMode::WifiAp {
-                        ssid: ap.ssid,
-                        rssi: ap.rssi,
-                        channel: ap.channel,
-                    }
-                )
-            );
+            top_bar::show(TopBarMode::WifiAp {
+                ssid: ap.ssid,
+                rssi: ap.rssi,
+                channel: ap.channel,
+            });
+        }
+
+        // scroll the newly selected label from its start
+        if before != (state.current() as *const Menu, state.selected) {
+            selected_since = Instant::now();
         }
 
-        render_menu(&mut display, &state, normal, inverted, VISIBLE_LINES);
+        animating = show(&mut frame, &state, dialog.as_ref(), normal, inverted, selected_since);
     }
 }
 
+/// Draw the open dialog, or the menu when there is none. Returns true
+/// while the selected label scrolls.
+fn show(
+    frame: &mut Frame,
+    state: &MenuState,
+    dialog: Option<&ActiveDialog>,
+    normal: MonoTextStyle<'static, BinaryColor>,
+    inverted: MonoTextStyle<'static, BinaryColor>,
+    selected_since: Instant,
+) -> bool {
+    match dialog {
+        Some(active) => {
+            active.draw(frame);
+            MENU_FRAMES.signal(frame.clone());
+            false
+        }
+        None => render_menu(frame, state, normal, inverted, visible_lines(), selected_since),
+    }
+}
+
+/// Item rows below the title on the menu panel.
+fn visible_lines() -> usize {
+    ((layout::get().menu_height() as i32 - TITLE_HEIGHT) / LINE_HEIGHT).max(1) as usize
+}
+
 fn render_menu(
-    display: &mut Display,
+    frame: &mut Frame,
     state: &MenuState,
     normal: MonoTextStyle<'static, BinaryColor>,
     inverted: MonoTextStyle<'static, BinaryColor>,
     visible_lines: usize,
-) {
+    selected_since: Instant,
+) -> bool {
     use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle};
 
-    display.clear_buffer();
+    let mut animating = false;
+
+    frame.clear_buffer();
     let menu = state.current();
 
-    // title
-    Text::with_baseline(menu.title, Point::new(0, 0), normal, Baseline::Top)
-        .draw(display)
-        .unwrap();
+    // title row: breadcrumb on the left, position on the right
+    let small = FontSize::Small.style(BinaryColor::On);
+    let position = if menu.items.is_empty() {
+        String::from("0/0")
+    } else {
+        format!("{}/{}", state.selected + 1, menu.items.len())
+    };
+    let position_width = text::width(&position, FontSize::Small);
+    text::draw(frame, &position, (WIDTH as u32 - position_width) as i32, 0, small);
+
+    let titles: Vec<&str> = state.stack.iter().map(|level| level.menu.title).collect();
+    let crumb_width = (WIDTH as u32).saturating_sub(position_width + 4);
+    text::draw(frame, &breadcrumb(&titles, crumb_width, FontSize::Small), 0, 0, small);
+
+    let track_height = (frame.height() as i32 - TITLE_HEIGHT) as u32;
+    let thumb = scrollbar_thumb(menu.items.len(), visible_lines, state.scroll, track_height);
+    let row_width = match thumb {
+        Some((start, len)) => {
+            let x = (WIDTH as u32 - SCROLLBAR_WIDTH) as i32;
+            Rectangle::new(Point::new(x, TITLE_HEIGHT + start as i32), Size::new(SCROLLBAR_WIDTH, len))
+                .into_styled(PrimitiveStyleBuilder::new().fill_color(BinaryColor::On).build())
+                .draw(frame)
+                .unwrap();
+            WIDTH as u32 - SCROLLBAR_SPACE
+        }
+        None => WIDTH as u32,
+    };
 
     for i in 0..visible_lines {
         let idx = state.scroll + i;
@@ -324,7 +704,7 @@ fn render_menu(
 
         let is_selected_ap = match (
             &menu.items[idx].action,
-            get_selected_ap(),
+            app_state::get().selected_ap,
         ) {
             (MenuAction::WifiAp(ap), Some(sel)) => core::ptr::eq(*ap, sel),
             _ => false,
@@ -336,84 +716,113 @@ fn render_menu(
             alloc::format!("{}", menu.items[idx].label)
         };
 
-        if idx == state.selected {
+        let selected = idx == state.selected;
+        let style = if selected { inverted } else { normal };
 
-            Rectangle::new(Point::new(0, y), Size::new(128, LINE_HEIGHT as u32))
+        if selected {
+            Rectangle::new(Point::new(0, y), Size::new(row_width, LINE_HEIGHT as u32))
                 .into_styled(PrimitiveStyleBuilder::new().fill_color(BinaryColor::On).build())
-                .draw(display)
+                .draw(frame)
                 .unwrap();
+        }
 
-            Text::with_baseline(label.as_str(), Point::new(0, y), inverted, Baseline::Top)
-                .draw(display)
-                .unwrap();
+        // value right aligned, the label gets what is left
+        let mut label_width = row_width;
+        if let Some(value) = menu.items[idx].action.value_text(selected && state.editing) {
+            let value = text::ellipsize(&value, row_width / 2, FontSize::Normal);
+            let value_width = text::width(&value, FontSize::Normal);
+            text::draw(frame, &value, (row_width - value_width) as i32, y, style);
+            label_width = row_width - value_width - VALUE_GAP;
+        }
+
+        if selected {
+            let area = Rectangle::new(Point::new(0, y), Size::new(label_width, LINE_HEIGHT as u32));
+            MENU_MARQUEE.draw(frame, &label, area, selected_since, FontSize::Normal, inverted);
+            animating = Marquee::scrolls(text::width(&label, FontSize::Normal), label_width);
         } else {
+            let label = text::ellipsize(&label, label_width, FontSize::Normal);
             Text::with_baseline(label.as_str(), Point::new(0, y), normal, Baseline::Top)
-                .draw(display)
+                .draw(frame)
                 .unwrap();
         }
     }
 
-    display.flush().unwrap();
+    MENU_FRAMES.signal(frame.clone());
+    animating
 }
 
 #[embassy_executor::task]
 pub async fn radio_task() {
     loop {
-        match MENU_CMD_CH.receive().await {
-            MenuCommand::WifiDeauthSelected => {
-                if let Some(ap) = get_selected_ap() {
-                    wifi_deauth_test(ap).await;
-                } else {
-                    info!("No WiFi AP selected");
-                }
-            }
-
+        watchdog::wait(Task::Radio);
+        let cmd = MENU_CMD_CH.receive().await;
+        watchdog::check_in(Task::Radio);
+        match cmd {
             MenuCommand::WifiClearSelected => {
-                // SELECTED_WIFI_AP.store(core::ptr::null(), Ordering::Relaxed);
+                app_state::select_ap(None);
                 info!("WiFi selection cleared");
             }
 
-            MenuCommand::ToggleBluetooth => {
-                info!("Toggling Bluetooth");
+            MenuCommand::ClockHourUp | MenuCommand::ClockMinuteUp => {
+                clock::adjust(if let MenuCommand::ClockHourUp = cmd { 3600 } else { 300 });
+                let (hh, mm) = clock::now_hhmm();
+                notice("Clock", format!("{:02}:{:02}", hh, mm));
+            }
+
+            MenuCommand::ResetTopBar => {
+                settings::update(|s| s.top_bar = BarLayout::new());
+                notice("Top Bar", String::from("default"));
             }
 
             MenuCommand::Reboot => {
                 esp_hal::system::software_reset();
             }
 
-            _ => {}
-        }
-    }
-}
+            MenuCommand::PowerOff => power::power_off(),
 
-async fn wifi_deauth_test(ap: &'static WifiApInfo) {
-    info!(
-        "Deauth test requested for SSID='{}' CH={} RSSI={}",
-        ap.ssid,
-        ap.channel,
-        ap.rssi
-    );
+            MenuCommand::FactoryReset => {
+                dialog::progress("Factory Reset", String::from("Erasing...")).await;
+                for (i, file) in FACTORY_RESET_FILES.iter().enumerate() {
+                    storage::delete_file(file).await;
+                    dialog::set_progress(((i + 1) * 100 / FACTORY_RESET_FILES.len()) as u8);
+                }
+                info!("Factory reset done, rebooting");
+                esp_hal::system::software_reset();
+            }
 
-    // 1️⃣ Switch radio to AP channel (allowed)
-    // esp_wifi_set_channel(ap.channel, WIFI_SECOND_CHAN_NONE);
+            MenuCommand::SaveCrash => {
+                let message = if crash::save().await {
+                    format!("Saved to {}", crash::LOG_FILE)
+                } else {
+                    String::from("Not saved")
+                };
+                dialog::alert("Last crash", message, Duration::from_secs(ALERT_SECS)).await;
+            }
 
-    // 2️⃣ Placeholder: we CANNOT enumerate stations on ESP32
-    info!("ESP32 cannot enumerate stations of foreign APs");
+            MenuCommand::DeleteFile(file) => {
+                let message = if storage::delete_file(file).await {
+                    format!("{} deleted", file)
+                } else {
+                    format!("{} not deleted", file)
+                };
+                dialog::alert("Delete", message, Duration::from_secs(ALERT_SECS)).await;
+            }
 
-    // 3️⃣ Placeholder loop for future injector
-    for i in 0..5 {
-        info!("(stub) Would send deauth burst {}", i);
-        embassy_time::Timer::after_millis(100).await;
+            _ => {}
+        }
     }
+}
 
-    info!("Deauth test completed (stub)");
+fn notice(title: &'static str, detail: String) {
+    top_bar::show(TopBarMode::Notice { title, detail });
 }
 
 #[embassy_executor::task]
 pub async fn ble_scan_task() {
+    // let mut events = bus::UI.subscribe();
     // loop {
-    //     match MENU_CMD_CH.receive().await {
-    //         MenuCommand::BleScan => {
+    //     match events.next().await {
+    //         UiEvent::Command(MenuCommand::BleScan) => {
     //             info!("Starting BLE scan...");
     //
     //             // pseudo-code: scan for BLE devices
@@ -429,7 +838,7 @@ pub async fn ble_scan_task() {
     //             // let dyn_menu = create_dynamic_menu("BLE Devices", &labels);
     //             //
     //             // // send dynamic menu command to menu task
-    //             // MENU_CMD_CH.send(MenuCommand::EnterDynamic(dyn_menu)).await;
+    //             // bus::UI.publish(UiEvent::PushMenu(dyn_menu));
     //         }
     //         _ => {}
     //     }
@@ -438,35 +847,117 @@ pub async fn ble_scan_task() {
 
 #[embassy_executor::task]
 pub async fn wifi_scan_task(
-    wifi: &'static mut WifiController<'static>,
+    mut wifi: Option<&'static mut WifiController<'static>>,
 ) {
     loop {
-        WIFI_SCAN_CH.receive().await;
+        watchdog::wait(Task::WifiScan);
+        let request = WIFI_SCAN_CH.receive().await;
+        watchdog::check_in(Task::WifiScan);
+
+        let controller = match (wifi.as_deref_mut(), battery_policy::radios_allowed()) {
+            (Some(controller), true) => controller,
+            (present, _) => {
+                let reason = if present.is_some() { "Battery too low" } else { "WiFi unavailable" };
+                match request {
+                    WifiScanRequest::RogueMonitor => rogue_ap::SCAN_RESULT_CH.send(Vec::new()).await,
+                    WifiScanRequest::Menu => {
+                        dialog::alert("WiFi Scan", String::from(reason), Duration::from_secs(ALERT_SECS)).await;
+                    }
+                }
+                continue;
+            }
+        };
+
+        app_state::set_radio(RadioState::Scanning);
+        let result = match request {
+            WifiScanRequest::Menu => scan_with_progress(controller).await,
+            WifiScanRequest::RogueMonitor => controller.scan_with_config_async(ScanConfig::default()).await,
+        };
+        app_state::set_radio(RadioState::Idle);
 
-        let result = match wifi.scan_with_config(ScanConfig::default()) {
+        let result = match result {
             Ok(r) => r,
-            Err(_) => continue,
+            Err(_) => {
+                // the monitor is waiting for an answer either way
+                match request {
+                    WifiScanRequest::RogueMonitor => rogue_ap::SCAN_RESULT_CH.send(Vec::new()).await,
+                    WifiScanRequest::Menu => {
+                        dialog::alert("WiFi Scan", String::from("Scan failed"), Duration::from_secs(ALERT_SECS)).await;
+                    }
+                }
+                continue;
+            }
         };
 
-        let mut aps = Vec::new();
+        match request {
+            WifiScanRequest::Menu => {
+                let mut aps = Vec::new();
 
-        for ap in result {
-            let ssid: &'static str =
-                Box::leak(ap.ssid.clone().into_boxed_str());
+                for ap in result {
+                    let ssid: &'static str =
+                        Box::leak(ap.ssid.clone().into_boxed_str());
 
-            aps.push(WifiApInfo {
-                ssid,
-                rssi: ap.signal_strength,
-                channel: ap.channel,
-                // auth: ap.auth_method,
-            });
+                    aps.push(WifiApInfo {
+                        ssid,
+                        bssid: ap.bssid,
+                        rssi: ap.signal_strength,
+                        channel: ap.channel,
+                        auth: auth_from(ap.auth_method),
+                    });
+                }
+
+                let menu = build_wifi_menu(aps);
+                dialog::close().await;
+
+                led::request(
+                    LedSource::Notification,
+                    Priority::Notification,
+                    Pattern::Solid(colors::GREEN),
+                    Some(Duration::from_millis(500)),
+                );
+                MENU_REQ_CH.send(MenuRequest::PushMenu(menu)).await;
+            }
+            WifiScanRequest::RogueMonitor => {
+                let scan = result
+                    .into_iter()
+                    .map(|ap| Sighting {
+                        ssid: ap.ssid,
+                        bssid: ap.bssid,
+                        auth: auth_from(ap.auth_method),
+                        channel: ap.channel,
+                    })
+                    .collect();
+
+                rogue_ap::SCAN_RESULT_CH.send(scan).await;
+            }
         }
+    }
+}
 
-        let menu = build_wifi_menu(aps);
+/// Scan one channel at a time, so the progress dialog of the menu can
+/// follow along.
+async fn scan_with_progress(
+    controller: &mut WifiController<'static>,
+) -> Result<Vec<AccessPointInfo>, WifiError> {
+    let mut found = Vec::new();
+    for channel in 1..=SCAN_CHANNELS {
+        watchdog::check_in(Task::WifiScan);
+        let config = ScanConfig::default().with_channel(channel);
+        found.extend(controller.scan_with_config_async(config).await?);
+        dialog::set_progress(channel * 100 / SCAN_CHANNELS);
+    }
+    Ok(found)
+}
 
-        MENU_MSG_CH
-            .send(MenuMsg::PushMenu(menu))
-            .await;
+fn auth_from(method: Option<AuthMethod>) -> Auth {
+    match method {
+        None | Some(AuthMethod::None) => Auth::Open,
+        Some(AuthMethod::Wep) => Auth::Wep,
+        Some(AuthMethod::Wpa) => Auth::Wpa,
+        Some(AuthMethod::Wpa2Personal) | Some(AuthMethod::WpaWpa2Personal) => Auth::Wpa2,
+        Some(AuthMethod::Wpa3Personal) | Some(AuthMethod::Wpa2Wpa3Personal) => Auth::Wpa3,
+        Some(AuthMethod::Wpa2Enterprise) => Auth::Enterprise,
+        Some(_) => Auth::Other,
     }
 }
 
@@ -487,58 +978,3 @@ fn build_wifi_menu(aps: Vec<WifiApInfo>) -> &'static Menu {
         items: Box::leak(items.into_boxed_slice()),
     }))
 }
-
-fn build_wifi_ap_action_menu(ap: &'static WifiApInfo) -> &'static Menu {
-    let items = vec![
-        MenuItem {
-            label: "Connect",
-            // action: MenuAction::Trigger(MenuCommand::WifiConnect(ap)),
-            action: MenuAction::Trigger(MenuCommand::Reboot)
-        },
-        MenuItem {
-            label: "Deauth Test",
-            // action: MenuAction::Trigger(MenuCommand::WifiDeauth(ap)),
-            action: MenuAction::Trigger(MenuCommand::Reboot)
-        },
-    ];
-
-    Box::leak(Box::new(Menu {
-        title: ap.ssid,
-        items: Box::leak(items.into_boxed_slice()),
-    }))
-}
-
-pub fn set_selected_ap(ap: &'static WifiApInfo) {
-    SELECTED_WIFI_AP.store(ap as *const _ as *mut _, Ordering::Relaxed);
-}
-
-pub fn get_selected_ap() -> Option<&'static WifiApInfo> {
-    let ptr = SELECTED_WIFI_AP.load(Ordering::Relaxed);
-    if ptr.is_null() {
-        None
-    } else {
-        Some(unsafe { &*ptr })
-    }
-}
-
-fn normalize_menu_state(state: &mut MenuState) {
-    let len = state.current().items.len();
-
-    if len == 0 {
-        state.selected = 0;
-        state.scroll = 0;
-        return;
-    }
-
-    if state.selected >= len {
-        state.selected = len - 1;
-    }
-
-    if state.scroll > state.selected {
-        state.scroll = state.selected;
-    }
-
-    if state.selected >= state.scroll + VISIBLE_LINES {
-        state.scroll = state.selected + 1 - VISIBLE_LINES;
-    }
-}
@@ -1,2 +1,8 @@
+pub mod dialog;
+pub mod display;
+pub mod layout;
+pub mod marquee;
 pub mod menu;
+pub mod screen;
+pub mod sh1106;
 pub mod top_bar;
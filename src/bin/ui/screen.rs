@@ -0,0 +1,69 @@
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::settings;
+
+use bitband::screen::state_for;
+pub use bitband::screen::ScreenState;
+
+const POLL_MS: u64 = 250;
+const DIM_PERCENT: u8 = 5;
+
+static STATE: AtomicU8 = AtomicU8::new(ScreenState::On as u8);
+static LAST_ACTIVITY: Mutex<CriticalSectionRawMutex, Cell<Instant>> =
+    Mutex::new(Cell::new(Instant::from_ticks(0)));
+
+pub fn state() -> ScreenState {
+    match STATE.load(Ordering::Relaxed) {
+        0 => ScreenState::On,
+        1 => ScreenState::Dimmed,
+        _ => ScreenState::Off,
+    }
+}
+
+/// Record user activity. Returns true if the panels were off, in which
+/// case the input only woke the screen and should not be acted upon.
+pub fn wake() -> bool {
+    LAST_ACTIVITY.lock(|t| t.set(Instant::now()));
+    let was = state();
+    STATE.store(ScreenState::On as u8, Ordering::Relaxed);
+    was == ScreenState::Off
+}
+
+/// Switch the panels off right away, before the device goes to sleep.
+pub fn sleep() {
+    STATE.store(ScreenState::Off as u8, Ordering::Relaxed);
+}
+
+/// Time since the last button press.
+pub fn idle() -> Duration {
+    Instant::now() - LAST_ACTIVITY.lock(|t| t.get())
+}
+
+/// Brightness the panels should use right now, `None` while they are off.
+pub fn panel_brightness() -> Option<u8> {
+    match state() {
+        ScreenState::On => Some(settings::brightness()),
+        ScreenState::Dimmed => Some(settings::brightness().min(DIM_PERCENT)),
+        ScreenState::Off => None,
+    }
+}
+
+#[embassy_executor::task]
+pub async fn screen_task() {
+    LAST_ACTIVITY.lock(|t| t.set(Instant::now()));
+
+    loop {
+        let next = state_for(idle().as_secs(), settings::get().screen_timeout_secs);
+
+        // never turn the screen back on from here, only `wake` does that
+        if next as u8 > state() as u8 {
+            STATE.store(next as u8, Ordering::Relaxed);
+        }
+
+        Timer::after(Duration::from_millis(POLL_MS)).await;
+    }
+}
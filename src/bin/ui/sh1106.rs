@@ -0,0 +1,69 @@
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+
+use crate::frame::{FrameSink, Region};
+
+/// The 132 column GDDRAM is centred on the 128 column glass.
+const COLUMN_OFFSET: u8 = 2;
+/// Power-on sequence. Close to the SSD1306 one, but the charge pump
+/// has its own command.
+const INIT: [u8; 23] = [
+    0xAE, // display off
+    0xD5, 0x80, // clock divide
+    0xA8, 0x3F, // multiplex 64
+    0xD3, 0x00, // display offset
+    0x40, // start line 0
+    0xAD, 0x8B, // charge pump on
+    0xA1, // segment remap
+    0xC8, // scan COM63 to COM0
+    0xDA, 0x12, // alternative COM pins
+    0x81, 0x80, // contrast
+    0xD9, 0x22, // precharge
+    0xDB, 0x35, // VCOMH
+    0xA4, // show GDDRAM
+    0xA6, // not inverted
+    0xAF, // display on
+];
+
+/// 128x64 SH1106 panel. Only page addressing, so regions are written
+/// one page at a time.
+pub struct Sh1106<DI> {
+    interface: DI,
+}
+
+impl<DI: AsyncWriteOnlyDataCommand> Sh1106<DI> {
+    pub fn new(interface: DI) -> Self {
+        Self { interface }
+    }
+
+    pub async fn init(&mut self) -> Result<(), DisplayError> {
+        self.command(&INIT).await
+    }
+
+    async fn command(&mut self, bytes: &[u8]) -> Result<(), DisplayError> {
+        self.interface.send_commands(DataFormat::U8(bytes)).await
+    }
+}
+
+impl<DI: AsyncWriteOnlyDataCommand> FrameSink for Sh1106<DI> {
+    type Error = DisplayError;
+
+    async fn set_power(&mut self, on: bool) -> Result<(), DisplayError> {
+        self.command(&[if on { 0xAF } else { 0xAE }]).await
+    }
+
+    async fn set_level(&mut self, percent: u8) -> Result<(), DisplayError> {
+        let contrast = (percent.min(100) as u16 * 255 / 100) as u8;
+        self.command(&[0x81, contrast]).await
+    }
+
+    async fn write_region(&mut self, region: Region, data: &[u8]) -> Result<(), DisplayError> {
+        let width = (region.end_col - region.start_col) as usize;
+        let col = region.start_col + COLUMN_OFFSET;
+
+        for (page, row) in (region.first_page..=region.last_page).zip(data.chunks(width)) {
+            self.command(&[0xB0 | page, col & 0x0F, 0x10 | (col >> 4)]).await?;
+            self.interface.send_data(DataFormat::U8(row)).await?;
+        }
+        Ok(())
+    }
+}
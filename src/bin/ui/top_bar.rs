@@ -1,18 +1,13 @@
 use bt_hci::event::le::LeBigSyncEstablished;
-use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    channel::Channel,
-    signal::Signal,
-};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::text::renderer::CharacterStyle;
 use ssd1306::{prelude::*, mode::BufferedGraphicsMode};
 use esp_hal::i2c;
 use esp_hal::peripherals;
 use ssd1306::{mode::TerminalMode, prelude::*, I2CDisplayInterface, Ssd1306, command};
 use embedded_graphics::{
-    mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
     prelude::*,
     text::{Baseline, Text}
@@ -20,18 +15,24 @@ use embedded_graphics::{
 use defmt::info;
 
 use alloc::format;
+use alloc::string::String;
 
-use crate::menu::{WifiApInfo, get_selected_ap};
-
-#[derive(Copy, Clone)]
-pub struct StatusBar {
-    pub battery_percent: u8,
-    pub minutes: u8,
-    pub hours: u8,
-}
+use core::sync::atomic::{AtomicU8, Ordering};
 
-pub static STATUS_SIGNAL: Signal<CriticalSectionRawMutex, StatusBar> =
-    Signal::new();
+use crate::app_state;
+use crate::bus::{self, TopicSubscriber};
+use crate::display::TOP_BAR_FRAMES;
+use crate::frame::{self, Frame};
+use crate::layout;
+use crate::icons;
+use crate::marquee::{Marquee, MarqueeMode};
+use crate::rogue_ap::Auth;
+use crate::screen;
+use crate::settings;
+use crate::storage;
+use crate::text::{self, FontSize};
+use crate::watchdog::{self, Task};
+use crate::widget::{self, BarStatus, Widget};
 
 // type Display = Ssd1306<
 //     I2CDisplayInterface,
@@ -39,158 +40,223 @@ pub static STATUS_SIGNAL: Signal<CriticalSectionRawMutex, StatusBar> =
 //     BufferedGraphicsMode<DisplaySize128x32>,
 // >;
 
-pub enum TopInfo {
-    WifiAp(&'static WifiApInfo),
-    Default,
-}
-
+#[derive(Clone)]
 pub enum TopBarMode {
-    Normal {
-        battery_percent: u8,
-        time_hhmm: (u8, u8),
-    },
+    /// The widget bar.
+    Normal,
     WifiAp {
         ssid: &'static str,
         rssi: i8,
         channel: u8,
         // auth: AuthMethod,
     },
+    Alert {
+        title: &'static str,
+        detail: String,
+    },
+    DeauthMonitor {
+        frames: u32,
+        flooding: bool,
+        /// Channel the sniffer listens on, 0 before the first frame.
+        channel: u8,
+    },
+    Notice {
+        title: &'static str,
+        detail: String,
+    },
+}
+
+impl TopBarMode {
+    /// How long the mode stays up before the bar goes back to normal,
+    /// unless it is sent again.
+    fn timeout(&self) -> Option<Duration> {
+        match self {
+            TopBarMode::Normal => None,
+            TopBarMode::WifiAp { .. } | TopBarMode::Alert { .. } => Some(Duration::from_secs(30)),
+            TopBarMode::Notice { .. } => Some(Duration::from_secs(5)),
+            // sent every second while the monitor runs
+            TopBarMode::DeauthMonitor { .. } => Some(Duration::from_secs(3)),
+        }
+    }
+}
+
+/// Put `mode` on the top display.
+pub fn show(mode: TopBarMode) {
+    bus::TOP_BAR.publish(mode);
 }
 
-pub static TOP_BAR_CH: Channel<
-    CriticalSectionRawMutex,
-    TopBarMode,
-    4,
-> = Channel::new();
+/// Alerts and notices that came in since the last button press.
+static NOTIFICATIONS: AtomicU8 = AtomicU8::new(0);
 
-type Display = ssd1306::Ssd1306<ssd1306::prelude::I2CInterface<esp_hal::i2c::master::I2c<'static, esp_hal::Blocking>>, DisplaySize128x32, BufferedGraphicsMode<DisplaySize128x32>>;
+pub fn clear_notifications() {
+    NOTIFICATIONS.store(0, Ordering::Relaxed);
+}
 
+/// `events` is taken by main before anything that publishes to it is
+/// spawned.
 #[embassy_executor::task]
-pub async fn status_task(mut display: Display) {
-    let mut state = TopBarMode::Normal {
-        battery_percent: 100,
-        time_hhmm: (0, 0),
-    };
+pub async fn status_task(mut events: TopicSubscriber<TopBarMode, 8>) {
+    let mut state = TopBarMode::Normal;
     let mut tick: u32 = 0;
+    let mut frame = Frame::new(layout::get().top_bar_height());
+    let mut shown_since = Instant::now();
 
-    let style = MonoTextStyleBuilder::new()
-        .font(&FONT_6X10)
-        .text_color(BinaryColor::On)
-        .build();
+    let style = FontSize::Normal.style(BinaryColor::On);
 
     loop {
+        watchdog::check_in(Task::Status);
         tick = tick.wrapping_add(1);
 
-        if let Ok(msg) = TOP_BAR_CH.try_receive() {
-            state = match msg {
-                TopBarMode::Normal { battery_percent, time_hhmm } => TopBarMode::Normal { battery_percent, time_hhmm },
-                TopBarMode::WifiAp { ssid, rssi, channel } =>
-                    TopBarMode::WifiAp { ssid, rssi, channel },
-            };
+        while let Some(msg) = events.try_next() {
+            // the monitor status is resent every second, it must not
+            // push a fresh alert off the bar
+            if matches!(msg, TopBarMode::DeauthMonitor { .. })
+                && matches!(state, TopBarMode::Alert { .. })
+                && state.timeout().is_some_and(|timeout| shown_since.elapsed() < timeout)
+            {
+                continue;
+            }
+            if let TopBarMode::Alert { .. } | TopBarMode::Notice { .. } = msg {
+                let count = NOTIFICATIONS.load(Ordering::Relaxed);
+                NOTIFICATIONS.store(count.saturating_add(1), Ordering::Relaxed);
+            }
+            state = msg;
+            shown_since = Instant::now();
+        }
+        if state.timeout().is_some_and(|timeout| shown_since.elapsed() >= timeout) {
+            state = TopBarMode::Normal;
+        }
+
+        // nothing to draw while the panel is off
+        if screen::panel_brightness().is_none() {
+            Timer::after_millis(100).await;
+            continue;
         }
 
-        display.clear_buffer();
+        frame.clear_buffer();
+        let bar = Rectangle::new(Point::zero(), frame.size());
 
-        match state {
-            TopBarMode::Normal {battery_percent, time_hhmm} => {
-                BatteryWidget.draw(&mut display, tick, style);
-                ClockWidget.draw(&mut display, tick, style);
+        match &state {
+            TopBarMode::Normal => {
+                let app = app_state::get();
+                let status = BarStatus {
+                    battery_percent: app.battery.percent,
+                    charging: app.battery.charging,
+                    time_hhmm: app.time_hhmm,
+                    wifi_rssi: app.selected_ap.map(|ap| ap.rssi),
+                    bluetooth: app.bluetooth,
+                    sd_ok: storage::card_ok(),
+                    notifications: NOTIFICATIONS.load(Ordering::Relaxed),
+                    free_heap: esp_alloc::HEAP.free(),
+                };
+                widget::draw_bar(&mut frame, &settings::get().top_bar, &status, tick, style);
             }
             TopBarMode::WifiAp { ssid, rssi, channel } => {
-                WifiApWidget.draw(&mut display, tick, style);
+                WifiApWidget { since: shown_since }.draw(&mut frame, bar, tick, style);
+            }
+            TopBarMode::Alert { title, detail } => {
+                AlertWidget { title, detail, since: shown_since }.draw(&mut frame, bar, tick, style);
+            }
+            TopBarMode::DeauthMonitor { frames, flooding, channel } => {
+                DeauthWidget { frames: *frames, flooding: *flooding, channel: *channel }.draw(&mut frame, bar, tick, style);
+            }
+            TopBarMode::Notice { title, detail } => {
+                text::draw(&mut frame, title, 0, 0, style);
+                draw_wrapped(&mut frame, detail, 10, style);
             }
         }
 
-        display.flush().unwrap();
+        TOP_BAR_FRAMES.signal(frame.clone());
         Timer::after_millis(100).await;
     }
 }
 
-trait Widget {
-    fn draw(&mut self, display: &mut Display, tick: u32, style: MonoTextStyle<'_, BinaryColor>);
-}
+/// Scrolls a long field of the top bar, 10 px/s is one pixel per frame.
+const FIELD_MARQUEE: Marquee = Marquee::new(MarqueeMode::Wrap).with_speed(10);
 
-pub struct BatteryWidget;
+pub struct WifiApWidget {
+    pub since: Instant,
+}
 
-impl Widget for BatteryWidget {
-    fn draw(&mut self, display: &mut Display, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
-        // let pct = get_battery_percent(); // your existing function
-        let pct = 100;
-        draw_text_at(display, &format!("BAT:{}%", pct), 0, 0, style);
+impl Widget for WifiApWidget {
+    fn preferred_width(&self) -> u32 {
+        frame::WIDTH as u32
     }
-}
 
-pub struct ClockWidget;
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        if let Some(ap) = app_state::get().selected_ap {
+            // SSID (scrolling)
+            let row = Rectangle::new(area.top_left, Size::new(area.size.width, 10));
+            FIELD_MARQUEE.draw(frame, ap.ssid, row, self.since, FontSize::Normal, style);
+
+            // Metadata
+            text::draw(frame, &format!("{}dBm  CH{}", ap.rssi, ap.channel), 0, 10, style);
 
-impl Widget for ClockWidget {
-    fn draw(&mut self, display: &mut Display, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
-        // let (hh, mm) = get_time_hm(); // RTC helper
-        let (hh, mm) = (0, 0);
-        draw_text_at(display, &format!("{:02}:{:02}", hh, mm), 90, 0, style);
+            if ap.auth != Auth::Open {
+                icons::draw(frame, &icons::LOCK, 120, 11);
+            }
+        }
     }
 }
 
-pub struct WifiApWidget;
+pub struct AlertWidget<'a> {
+    pub title: &'a str,
+    pub detail: &'a str,
+    pub since: Instant,
+}
 
-impl Widget for WifiApWidget {
-    fn draw(&mut self, display: &mut Display, tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
-        if let Some(ap) = get_selected_ap() {
-            // SSID (scrolling)
-            draw_scrolling_text(
-                display,
-                ap.ssid,
-                0,
-                0,
-                128,
-                tick,
-                style,
-            );
+impl Widget for AlertWidget<'_> {
+    fn preferred_width(&self) -> u32 {
+        frame::WIDTH as u32
+    }
 
-            // Metadata
-            draw_text_at(
-                display,
-                &format!("{}dBm  CH{}",
-                    0,
-                    // ap.signal_strenght,
-                    ap.channel),
-                0,
-                10,
-                style,
-            );
+    fn draw(&mut self, frame: &mut Frame, _area: Rectangle, tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        // blink the title so it stands out from the normal bar
+        if (tick / 5) % 2 == 0 {
+            icons::draw(frame, &icons::ALARM, 0, 1);
+            text::draw(frame, self.title, 10, 0, style);
         }
+        let row = Rectangle::new(Point::new(0, 10), Size::new(frame::WIDTH as u32, 10));
+        FIELD_MARQUEE.draw(frame, self.detail, row, self.since, FontSize::Normal, style);
     }
 }
 
-pub fn draw_text_at(display: &mut Display, data: &str, pos_x: i32, pos_y: i32, style: MonoTextStyle<'_, BinaryColor>) {
-    Text::with_baseline(
-        data,
-        Point::new(pos_x, pos_y),
-        style,
-        Baseline::Top,
-    )
-    .draw(display)
-    .ok();
+pub struct DeauthWidget {
+    pub frames: u32,
+    pub flooding: bool,
+    pub channel: u8,
 }
 
-fn draw_scrolling_text(
-    display: &mut Display,
-    text: &str,
-    x: i32,
-    y: i32,
-    max_width: i32,
-    tick: u32,
-    style: MonoTextStyle<'_, BinaryColor>
-) {
-    let w = text_width(text);
-
-    if w <= max_width {
-        draw_text_at(display, text, x, y, style);
-    } else {
-        let scroll = (tick / 2) as i32 % (w + 10);
-        draw_text_at(display, text, x - scroll, y, style);
+impl Widget for DeauthWidget {
+    fn preferred_width(&self) -> u32 {
+        frame::WIDTH as u32
+    }
+
+    fn draw(&mut self, frame: &mut Frame, _area: Rectangle, tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        match self.channel {
+            0 => text::draw(frame, "DEAUTH MONITOR", 0, 0, style),
+            channel => text::draw(frame, &format!("DEAUTH MONITOR ch{}", channel), 0, 0, style),
+        }
+        if !self.flooding || (tick / 5) % 2 == 0 {
+            let status = if self.flooding { "FLOOD" } else { "ok" };
+            text::draw(frame, &format!("{}/10s {}", self.frames, status), 0, 10, style);
+        }
     }
 }
 
-fn text_width(s: &str) -> i32 {
-    (s.len() as i32) * 6
+/// Word wrap `detail` over the rows from `y` down, the last row ends in
+/// an ellipsis if the text does not fit.
+fn draw_wrapped(frame: &mut Frame, detail: &str, y: i32, style: MonoTextStyle<'_, BinaryColor>) {
+    let line_height = FontSize::Normal.line_height() as i32;
+    let rows = ((frame.height() as i32 - y) / line_height).max(1) as usize;
+    let mut lines = text::wrap(detail, frame::WIDTH as u32, FontSize::Normal);
+
+    if lines.len() > rows {
+        let rest = lines.split_off(rows - 1).join(" ");
+        lines.push(text::ellipsize(&rest, frame::WIDTH as u32, FontSize::Normal));
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        text::draw(frame, line, 0, y + i as i32 * line_height, style);
+    }
 }
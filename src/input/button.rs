@@ -0,0 +1,7 @@
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ButtonEvent {
+    Up,
+    Down,
+    Select,
+    Back,
+}
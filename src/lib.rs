@@ -1 +1,17 @@
-#![no_std]
+//! The parts of the firmware that do not touch the hardware. They also
+//! build for the host, so they can be unit tested there:
+//!
+//! ```text
+//! cargo +stable test --lib --target x86_64-unknown-linux-gnu
+//! ```
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod input;
+mod services;
+mod ui;
+
+pub use input::button;
+pub use services::{battery_policy, deauth, led, power, rogue_ap, watchdog};
+pub use ui::{frame, icons, layout, menu, screen, text, widget};
@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+
+/// Points the charge has to recover by before a level is left, so a
+/// reading that wobbles around a threshold does not flap.
+const HYSTERESIS: u8 = 3;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Battery {
+    pub percent: u8,
+    pub charging: bool,
+    /// False for a placeholder while there is no gauge to read, the
+    /// policy leaves those alone.
+    pub measured: bool,
+}
+
+/// Ordered from healthy to empty.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum BatteryLevel {
+    Ok,
+    /// Warned about.
+    Low,
+    /// Scanning and Bluetooth are switched off.
+    Shedding,
+    /// Shut down to protect the cell.
+    Critical,
+}
+
+/// Charge in percent at or below which each level starts.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Thresholds {
+    pub warn: u8,
+    pub shed: u8,
+    pub critical: u8,
+}
+
+impl Thresholds {
+    pub fn level_for(&self, percent: u8) -> BatteryLevel {
+        if percent <= self.critical {
+            BatteryLevel::Critical
+        } else if percent <= self.shed {
+            BatteryLevel::Shedding
+        } else if percent <= self.warn {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Ok
+        }
+    }
+
+    /// Set the threshold of `level` to `percent` and move the others out
+    /// of its way, so each level still starts below the one before it.
+    /// Only at 0 do two thresholds meet. `Ok` has no threshold.
+    pub fn set(&mut self, level: BatteryLevel, percent: u8) {
+        match level {
+            BatteryLevel::Ok => return,
+            BatteryLevel::Low => self.warn = percent,
+            BatteryLevel::Shedding => self.shed = percent,
+            BatteryLevel::Critical => self.critical = percent,
+        }
+        if level >= BatteryLevel::Shedding {
+            self.warn = self.warn.max(self.shed.saturating_add(1));
+        }
+        if level == BatteryLevel::Critical {
+            self.shed = self.shed.max(self.critical.saturating_add(1));
+            self.warn = self.warn.max(self.shed.saturating_add(1));
+        }
+        if level <= BatteryLevel::Shedding {
+            self.shed = self.shed.min(self.warn.saturating_sub(1));
+            self.critical = self.critical.min(self.shed.saturating_sub(1));
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PolicyAction {
+    Warn(BatteryLevel),
+    ShedRadios,
+    RestoreRadios,
+    Shutdown,
+}
+
+/// Turns battery samples into actions. Levels get worse as soon as a
+/// threshold is crossed and better only `HYSTERESIS` points above it,
+/// or right away on the charger. Unmeasured samples change nothing.
+pub struct BatteryPolicy {
+    level: BatteryLevel,
+}
+
+impl BatteryPolicy {
+    pub const fn new() -> Self {
+        Self { level: BatteryLevel::Ok }
+    }
+
+    pub fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    pub fn update(&mut self, battery: Battery, limits: &Thresholds) -> Vec<PolicyAction> {
+        if !battery.measured {
+            return Vec::new();
+        }
+
+        let prev = self.level;
+        let next = if battery.charging {
+            BatteryLevel::Ok
+        } else {
+            let level = limits.level_for(battery.percent);
+            if level >= prev {
+                level
+            } else {
+                limits.level_for(battery.percent.saturating_sub(HYSTERESIS)).min(prev)
+            }
+        };
+        self.level = next;
+
+        let mut actions = Vec::new();
+        if next > prev {
+            actions.push(PolicyAction::Warn(next));
+            if next >= BatteryLevel::Shedding && prev < BatteryLevel::Shedding {
+                actions.push(PolicyAction::ShedRadios);
+            }
+            if next == BatteryLevel::Critical {
+                actions.push(PolicyAction::Shutdown);
+            }
+        } else if next < BatteryLevel::Shedding && prev >= BatteryLevel::Shedding {
+            actions.push(PolicyAction::RestoreRadios);
+        }
+        actions
+    }
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Thresholds = Thresholds { warn: 20, shed: 10, critical: 3 };
+
+    fn on_battery(percent: u8) -> Battery {
+        Battery { percent, charging: false, measured: true }
+    }
+
+    /// Feeds `trace` through a fresh policy, returning the actions with
+    /// the index of the sample that caused them.
+    fn replay(trace: &[Battery]) -> Vec<(usize, PolicyAction)> {
+        let mut policy = BatteryPolicy::new();
+        let mut out = Vec::new();
+        for (i, &sample) in trace.iter().enumerate() {
+            out.extend(policy.update(sample, &LIMITS).into_iter().map(|a| (i, a)));
+        }
+        out
+    }
+
+    #[test]
+    fn steady_discharge_steps_through_every_level() {
+        let trace: Vec<Battery> = (0..=100).rev().map(on_battery).collect();
+        assert_eq!(
+            replay(&trace),
+            [
+                (80, PolicyAction::Warn(BatteryLevel::Low)),
+                (90, PolicyAction::Warn(BatteryLevel::Shedding)),
+                (90, PolicyAction::ShedRadios),
+                (97, PolicyAction::Warn(BatteryLevel::Critical)),
+                (97, PolicyAction::Shutdown),
+            ]
+        );
+    }
+
+    #[test]
+    fn noise_around_a_threshold_warns_once() {
+        let trace: Vec<Battery> = [22, 20, 21, 20, 22, 19, 21, 20].into_iter().map(on_battery).collect();
+        assert_eq!(replay(&trace), [(1, PolicyAction::Warn(BatteryLevel::Low))]);
+    }
+
+    #[test]
+    fn recovery_needs_the_hysteresis() {
+        let trace: Vec<Battery> = [12, 10, 12, 13, 14].into_iter().map(on_battery).collect();
+        assert_eq!(
+            replay(&trace),
+            [
+                (0, PolicyAction::Warn(BatteryLevel::Low)),
+                (1, PolicyAction::Warn(BatteryLevel::Shedding)),
+                (1, PolicyAction::ShedRadios),
+                (4, PolicyAction::RestoreRadios),
+            ]
+        );
+    }
+
+    #[test]
+    fn charger_restores_at_once() {
+        let trace = [on_battery(8), Battery { percent: 8, charging: true, measured: true }];
+        assert_eq!(
+            replay(&trace),
+            [
+                (0, PolicyAction::Warn(BatteryLevel::Shedding)),
+                (0, PolicyAction::ShedRadios),
+                (1, PolicyAction::RestoreRadios),
+            ]
+        );
+    }
+
+    #[test]
+    fn setting_a_threshold_keeps_the_order() {
+        let mut limits = LIMITS;
+        limits.set(BatteryLevel::Shedding, 25);
+        assert_eq!(limits, Thresholds { warn: 26, shed: 25, critical: 3 });
+
+        limits.set(BatteryLevel::Low, 5);
+        assert_eq!(limits, Thresholds { warn: 5, shed: 4, critical: 3 });
+
+        limits.set(BatteryLevel::Critical, 10);
+        assert_eq!(limits, Thresholds { warn: 12, shed: 11, critical: 10 });
+
+        limits.set(BatteryLevel::Shedding, 0);
+        assert_eq!(limits, Thresholds { warn: 12, shed: 0, critical: 0 });
+    }
+
+    #[test]
+    fn unmeasured_samples_are_ignored() {
+        let placeholder = |percent| Battery { percent, charging: false, measured: false };
+        let trace: Vec<Battery> = (0..=100).rev().map(placeholder).collect();
+        assert_eq!(replay(&trace), []);
+
+        // nor do they undo a measured level
+        let mut policy = BatteryPolicy::new();
+        policy.update(on_battery(9), &LIMITS);
+        assert!(policy.update(placeholder(100), &LIMITS).is_empty());
+        assert_eq!(policy.level(), BatteryLevel::Shedding);
+    }
+}
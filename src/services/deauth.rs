@@ -0,0 +1,341 @@
+use alloc::vec::Vec;
+
+const BUCKET_MS: u64 = 1000;
+const WINDOW_BUCKETS: usize = 10;
+const BSSIDS_MAX: usize = 16;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FrameKind {
+    Deauth,
+    Disassoc,
+}
+
+/// Classify a raw 802.11 frame. Returns the kind and BSSID (addr3)
+/// for deauthentication and disassociation frames, `None` otherwise.
+pub fn classify(frame: &[u8]) -> Option<(FrameKind, [u8; 6])> {
+    if frame.len() < 22 {
+        return None;
+    }
+
+    let frame_type = (frame[0] >> 2) & 0b11;
+    let subtype = frame[0] >> 4;
+    if frame_type != 0 {
+        return None;
+    }
+
+    let kind = match subtype {
+        10 => FrameKind::Disassoc,
+        12 => FrameKind::Deauth,
+        _ => return None,
+    };
+
+    let mut bssid = [0u8; 6];
+    bssid.copy_from_slice(&frame[16..22]);
+    Some((kind, bssid))
+}
+
+/// Event counter over a sliding window of `WINDOW_BUCKETS` buckets
+/// of `BUCKET_MS` each.
+#[derive(Clone, Debug)]
+pub struct WindowCounter {
+    buckets: [u32; WINDOW_BUCKETS],
+    head: u64,
+}
+
+impl WindowCounter {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; WINDOW_BUCKETS],
+            head: 0,
+        }
+    }
+
+    pub fn add(&mut self, now_ms: u64) {
+        self.advance(now_ms);
+        self.buckets[(self.head % WINDOW_BUCKETS as u64) as usize] += 1;
+    }
+
+    pub fn total(&mut self, now_ms: u64) -> u32 {
+        self.advance(now_ms);
+        self.buckets.iter().sum()
+    }
+
+    fn advance(&mut self, now_ms: u64) {
+        let bucket = now_ms / BUCKET_MS;
+        if bucket <= self.head {
+            return;
+        }
+
+        if bucket - self.head >= WINDOW_BUCKETS as u64 {
+            self.buckets = [0; WINDOW_BUCKETS];
+        } else {
+            for b in self.head + 1..=bucket {
+                self.buckets[(b % WINDOW_BUCKETS as u64) as usize] = 0;
+            }
+        }
+        self.head = bucket;
+    }
+}
+
+impl Default for WindowCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FloodTarget {
+    Channel(u8),
+    Bssid([u8; 6]),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Flood {
+    pub target: FloodTarget,
+    pub count: u32,
+}
+
+struct Tracked<K> {
+    key: K,
+    counter: WindowCounter,
+    flooding: bool,
+    last_seen_ms: u64,
+}
+
+/// Counts deauth/disassoc frames per channel and per BSSID and reports
+/// a flood once when a window count reaches the threshold. A target can
+/// be reported again after its count has dropped back below it.
+pub struct FloodDetector {
+    threshold: u32,
+    channels: Vec<Tracked<u8>>,
+    bssids: Vec<Tracked<[u8; 6]>>,
+}
+
+impl FloodDetector {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            channels: Vec::new(),
+            bssids: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, now_ms: u64, channel: u8, bssid: [u8; 6]) -> Vec<Flood> {
+        let mut floods = Vec::new();
+
+        if let Some(count) = track(&mut self.channels, channel, now_ms, self.threshold, usize::MAX) {
+            floods.push(Flood { target: FloodTarget::Channel(channel), count });
+        }
+        if let Some(count) = track(&mut self.bssids, bssid, now_ms, self.threshold, BSSIDS_MAX) {
+            floods.push(Flood { target: FloodTarget::Bssid(bssid), count });
+        }
+
+        floods
+    }
+
+    /// Expire old counts. Returns the number of frames seen in the
+    /// current window across all channels.
+    pub fn refresh(&mut self, now_ms: u64) -> u32 {
+        let threshold = self.threshold;
+        let mut total = 0;
+
+        for tracked in self.channels.iter_mut() {
+            let count = tracked.counter.total(now_ms);
+            tracked.flooding &= count >= threshold;
+            total += count;
+        }
+        for tracked in self.bssids.iter_mut() {
+            let count = tracked.counter.total(now_ms);
+            tracked.flooding &= count >= threshold;
+        }
+
+        total
+    }
+
+    pub fn is_flooding(&self) -> bool {
+        self.channels.iter().any(|t| t.flooding) || self.bssids.iter().any(|t| t.flooding)
+    }
+}
+
+fn track<K: PartialEq + Copy>(
+    list: &mut Vec<Tracked<K>>,
+    key: K,
+    now_ms: u64,
+    threshold: u32,
+    cap: usize,
+) -> Option<u32> {
+    let idx = match list.iter().position(|t| t.key == key) {
+        Some(idx) => idx,
+        None => {
+            if list.len() >= cap {
+                // forget whatever has been quiet the longest
+                let stalest = list
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, t)| t.last_seen_ms)
+                    .map(|(i, _)| i)?;
+                list.swap_remove(stalest);
+            }
+            list.push(Tracked {
+                key,
+                counter: WindowCounter::new(),
+                flooding: false,
+                last_seen_ms: now_ms,
+            });
+            list.len() - 1
+        }
+    };
+
+    let tracked = &mut list[idx];
+    tracked.last_seen_ms = now_ms;
+    tracked.counter.add(now_ms);
+
+    let count = tracked.counter.total(now_ms);
+    if count >= threshold && !tracked.flooding {
+        tracked.flooding = true;
+        Some(count)
+    } else {
+        None
+    }
+}
+
+pub fn parse_threshold(contents: &str) -> Option<u32> {
+    contents.trim().parse().ok().filter(|&n| n > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AP: [u8; 6] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    const OTHER: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+    fn mgmt(subtype: u8, bssid: [u8; 6]) -> [u8; 24] {
+        let mut frame = [0u8; 24];
+        frame[0] = subtype << 4;
+        frame[16..22].copy_from_slice(&bssid);
+        frame
+    }
+
+    #[test]
+    fn classifies_deauth_and_disassoc() {
+        assert_eq!(classify(&mgmt(12, AP)), Some((FrameKind::Deauth, AP)));
+        assert_eq!(classify(&mgmt(10, AP)), Some((FrameKind::Disassoc, AP)));
+    }
+
+    #[test]
+    fn ignores_other_frames() {
+        // beacon
+        assert_eq!(classify(&mgmt(8, AP)), None);
+        // a data frame with the deauth subtype bits
+        let mut data = mgmt(12, AP);
+        data[0] |= 0b10 << 2;
+        assert_eq!(classify(&data), None);
+        // too short for addr3
+        assert_eq!(classify(&mgmt(12, AP)[..21]), None);
+    }
+
+    #[test]
+    fn window_keeps_ten_seconds() {
+        let mut counter = WindowCounter::new();
+        counter.add(0);
+        counter.add(500);
+        counter.add(4_200);
+        assert_eq!(counter.total(9_999), 3);
+        // the first bucket falls out
+        assert_eq!(counter.total(10_000), 1);
+        assert_eq!(counter.total(13_999), 1);
+        assert_eq!(counter.total(14_000), 0);
+    }
+
+    #[test]
+    fn window_resets_after_a_long_gap() {
+        let mut counter = WindowCounter::new();
+        for ms in (0..5_000).step_by(100) {
+            counter.add(ms);
+        }
+        assert_eq!(counter.total(5_000), 50);
+        counter.add(60_000);
+        assert_eq!(counter.total(60_000), 1);
+    }
+
+    #[test]
+    fn window_ignores_time_going_back() {
+        let mut counter = WindowCounter::new();
+        counter.add(3_000);
+        counter.add(2_000);
+        assert_eq!(counter.total(3_000), 2);
+    }
+
+    #[test]
+    fn flood_is_reported_once() {
+        let mut detector = FloodDetector::new(5);
+        for i in 0..4 {
+            assert!(detector.record(i * 100, 6, AP).is_empty());
+        }
+        let floods = detector.record(400, 6, AP);
+        assert_eq!(
+            floods,
+            [
+                Flood { target: FloodTarget::Channel(6), count: 5 },
+                Flood { target: FloodTarget::Bssid(AP), count: 5 },
+            ]
+        );
+        assert!(detector.is_flooding());
+        assert!(detector.record(500, 6, AP).is_empty());
+    }
+
+    #[test]
+    fn spread_over_bssids_floods_the_channel() {
+        let mut detector = FloodDetector::new(4);
+        assert!(detector.record(0, 1, AP).is_empty());
+        assert!(detector.record(100, 1, OTHER).is_empty());
+        assert!(detector.record(200, 1, AP).is_empty());
+        assert_eq!(
+            detector.record(300, 1, OTHER),
+            [Flood { target: FloodTarget::Channel(1), count: 4 }]
+        );
+    }
+
+    #[test]
+    fn flood_rearms_after_it_dies_down() {
+        let mut detector = FloodDetector::new(3);
+        for i in 0..3 {
+            detector.record(i * 100, 11, AP);
+        }
+        assert!(detector.is_flooding());
+
+        assert_eq!(detector.refresh(5_000), 3);
+        assert!(detector.is_flooding());
+        assert_eq!(detector.refresh(10_000), 0);
+        assert!(!detector.is_flooding());
+
+        for i in 0..2 {
+            assert!(detector.record(20_000 + i * 100, 11, AP).is_empty());
+        }
+        assert_eq!(detector.record(20_200, 11, AP).len(), 2);
+    }
+
+    #[test]
+    fn quietest_bssid_makes_room() {
+        let mut detector = FloodDetector::new(2);
+        detector.record(0, 1, AP);
+        for i in 0..BSSIDS_MAX as u8 {
+            detector.record(1_000, 1, [0, 0, 0, 0, 0, i]);
+        }
+        // AP was forgotten, its earlier frame no longer counts
+        assert!(!detector
+            .record(2_000, 2, AP)
+            .contains(&Flood { target: FloodTarget::Bssid(AP), count: 2 }));
+        assert_eq!(detector.bssids.len(), BSSIDS_MAX);
+    }
+
+    #[test]
+    fn threshold_from_config() {
+        assert_eq!(parse_threshold("30\n"), Some(30));
+        assert_eq!(parse_threshold(" 5 "), Some(5));
+        assert_eq!(parse_threshold("0"), None);
+        assert_eq!(parse_threshold("lots"), None);
+        assert_eq!(FloodDetector::new(0).threshold, 1);
+    }
+}
@@ -0,0 +1,276 @@
+use smart_leds::{colors, RGB8};
+
+/// Fades and the rainbow are stepped this often.
+pub const FRAME_MS: u32 = 20;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Pattern {
+    Off,
+    Solid(RGB8),
+    Blink { color: RGB8, on_ms: u32, off_ms: u32 },
+    Breathe { color: RGB8, period_ms: u32 },
+    Rainbow { period_ms: u32 },
+    Morse { color: RGB8, text: &'static str, unit_ms: u32 },
+}
+
+/// Higher priority requests hide lower ones until they are cleared or
+/// time out.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    Idle,
+    Status,
+    Notification,
+    Warning,
+    Alarm,
+}
+
+/// Color of `pattern` at `elapsed_ms` since it was started.
+pub fn frame_at(pattern: &Pattern, elapsed_ms: u32) -> RGB8 {
+    match *pattern {
+        Pattern::Off => colors::BLACK,
+        Pattern::Solid(color) => color,
+        Pattern::Blink { color, on_ms, off_ms } => {
+            let period = (on_ms + off_ms).max(1);
+            if elapsed_ms % period < on_ms { color } else { colors::BLACK }
+        }
+        Pattern::Breathe { color, period_ms } => {
+            let period = period_ms.max(2);
+            let half = period / 2;
+            let phase = elapsed_ms % period;
+            let level = if phase < half {
+                phase * 255 / half
+            } else {
+                (period - phase) * 255 / (period - half)
+            };
+            scale(color, level.min(255) as u8)
+        }
+        Pattern::Rainbow { period_ms } => {
+            let period = period_ms.max(1);
+            wheel(((elapsed_ms % period) * 256 / period) as u8)
+        }
+        Pattern::Morse { color, text, unit_ms } => {
+            if morse_is_on(text, elapsed_ms / unit_ms.max(1)) { color } else { colors::BLACK }
+        }
+    }
+}
+
+/// Milliseconds from `elapsed_ms` until the color of `pattern` next
+/// changes, `None` if it never does. Fades are stepped every `FRAME_MS`.
+pub fn next_change(pattern: &Pattern, elapsed_ms: u32) -> Option<u32> {
+    match *pattern {
+        Pattern::Off | Pattern::Solid(_) => None,
+        Pattern::Blink { on_ms: 0, .. } | Pattern::Blink { off_ms: 0, .. } => None,
+        Pattern::Blink { on_ms, off_ms, .. } => {
+            let period = on_ms + off_ms;
+            let phase = elapsed_ms % period;
+            Some(if phase < on_ms { on_ms - phase } else { period - phase })
+        }
+        Pattern::Breathe { .. } | Pattern::Rainbow { .. } => Some(FRAME_MS),
+        Pattern::Morse { text, unit_ms, .. } => {
+            let unit_ms = unit_ms.max(1);
+            let units = morse_units_left(text, elapsed_ms / unit_ms)?;
+            Some(units * unit_ms - elapsed_ms % unit_ms)
+        }
+    }
+}
+
+/// Successive frames of `pattern` sampled every `step_ms`.
+pub fn frames(pattern: Pattern, step_ms: u32) -> impl Iterator<Item = RGB8> {
+    (0u32..).map(move |i| frame_at(&pattern, i.wrapping_mul(step_ms)))
+}
+
+fn scale(color: RGB8, level: u8) -> RGB8 {
+    let s = |c: u8| ((c as u16 * level as u16) / 255) as u8;
+    RGB8::new(s(color.r), s(color.g), s(color.b))
+}
+
+fn wheel(pos: u8) -> RGB8 {
+    match pos {
+        0..=84 => RGB8::new(255 - pos * 3, pos * 3, 0),
+        85..=169 => {
+            let pos = pos - 85;
+            RGB8::new(0, 255 - pos * 3, pos * 3)
+        }
+        _ => {
+            let pos = pos - 170;
+            RGB8::new(pos * 3, 0, 255 - pos * 3)
+        }
+    }
+}
+
+fn morse_code(c: char) -> Option<&'static str> {
+    let code = match c.to_ascii_uppercase() {
+        'A' => ".-", 'B' => "-...", 'C' => "-.-.", 'D' => "-..", 'E' => ".",
+        'F' => "..-.", 'G' => "--.", 'H' => "....", 'I' => "..", 'J' => ".---",
+        'K' => "-.-", 'L' => ".-..", 'M' => "--", 'N' => "-.", 'O' => "---",
+        'P' => ".--.", 'Q' => "--.-", 'R' => ".-.", 'S' => "...", 'T' => "-",
+        'U' => "..-", 'V' => "...-", 'W' => ".--", 'X' => "-..-", 'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----", '1' => ".----", '2' => "..---", '3' => "...--", '4' => "....-",
+        '5' => ".....", '6' => "-....", '7' => "--...", '8' => "---..", '9' => "----.",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Walk the on/off segments of `text` in morse units: dot 1, dash 3,
+/// 1 between symbols, 3 between letters and 7 between words. The text
+/// ends with a word gap so it can be looped. Stops when `f` returns false.
+fn for_each_segment(text: &str, mut f: impl FnMut(bool, u32) -> bool) {
+    for c in text.chars() {
+        if c == ' ' {
+            // 3 units were already added after the previous letter
+            if !f(false, 4) {
+                return;
+            }
+            continue;
+        }
+
+        let Some(code) = morse_code(c) else {
+            continue;
+        };
+
+        for (i, symbol) in code.bytes().enumerate() {
+            let on = if symbol == b'-' { 3 } else { 1 };
+            let gap = if i + 1 < code.len() { 1 } else { 3 };
+            if !f(true, on) || !f(false, gap) {
+                return;
+            }
+        }
+    }
+    f(false, 4);
+}
+
+/// Length of `text` in morse units, gaps included.
+fn morse_len(text: &str) -> u32 {
+    let mut total = 0;
+    for_each_segment(text, |_, len| {
+        total += len;
+        true
+    });
+    total
+}
+
+/// The segment `unit` falls in, looping over the text: whether it is on
+/// and how many units of it are left.
+fn morse_segment(text: &str, unit: u32) -> Option<(bool, u32)> {
+    let total = morse_len(text);
+    if total == 0 {
+        return None;
+    }
+
+    let mut pos = unit % total;
+    let mut segment = None;
+    for_each_segment(text, |is_on, len| {
+        if pos < len {
+            segment = Some((is_on, len - pos));
+            return false;
+        }
+        pos -= len;
+        true
+    });
+    segment
+}
+
+fn morse_is_on(text: &str, unit: u32) -> bool {
+    morse_segment(text, unit).is_some_and(|(on, _)| on)
+}
+
+fn morse_units_left(text: &str, unit: u32) -> Option<u32> {
+    morse_segment(text, unit).map(|(_, left)| left)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+
+    fn on_off(pattern: Pattern, step_ms: u32, count: usize) -> Vec<bool> {
+        frames(pattern, step_ms).take(count).map(|c| c != colors::BLACK).collect()
+    }
+
+    #[test]
+    fn steady_patterns_never_change() {
+        assert!(frames(Pattern::Solid(RED), 100).take(50).all(|c| c == RED));
+        assert!(frames(Pattern::Off, 100).take(50).all(|c| c == colors::BLACK));
+        assert_eq!(next_change(&Pattern::Solid(RED), 1234), None);
+        assert_eq!(next_change(&Pattern::Off, 0), None);
+    }
+
+    #[test]
+    fn blink_follows_its_duty_cycle() {
+        let blink = Pattern::Blink { color: RED, on_ms: 100, off_ms: 300 };
+        assert_eq!(
+            on_off(blink, 100, 8),
+            [true, false, false, false, true, false, false, false]
+        );
+        assert_eq!(next_change(&blink, 0), Some(100));
+        assert_eq!(next_change(&blink, 40), Some(60));
+        assert_eq!(next_change(&blink, 100), Some(300));
+        assert_eq!(next_change(&blink, 399), Some(1));
+    }
+
+    #[test]
+    fn blink_without_a_phase_is_steady() {
+        let dark = Pattern::Blink { color: RED, on_ms: 0, off_ms: 500 };
+        let lit = Pattern::Blink { color: RED, on_ms: 500, off_ms: 0 };
+        assert!(on_off(dark, 50, 20).iter().all(|on| !on));
+        assert!(on_off(lit, 50, 20).iter().all(|&on| on));
+        assert_eq!(next_change(&dark, 10), None);
+        assert_eq!(next_change(&lit, 10), None);
+    }
+
+    #[test]
+    fn breathe_peaks_halfway() {
+        let breathe = Pattern::Breathe { color: RED, period_ms: 1000 };
+        assert_eq!(frame_at(&breathe, 0), colors::BLACK);
+        assert_eq!(frame_at(&breathe, 500), RED);
+        assert_eq!(frame_at(&breathe, 1000), colors::BLACK);
+        assert!(frame_at(&breathe, 250).r < frame_at(&breathe, 400).r);
+        assert!(frame_at(&breathe, 600).r > frame_at(&breathe, 900).r);
+        assert_eq!(next_change(&breathe, 321), Some(FRAME_MS));
+    }
+
+    #[test]
+    fn rainbow_loops() {
+        let rainbow = Pattern::Rainbow { period_ms: 3000 };
+        assert_eq!(frame_at(&rainbow, 0), RGB8::new(255, 0, 0));
+        assert_eq!(frame_at(&rainbow, 3000), frame_at(&rainbow, 0));
+        assert_ne!(frame_at(&rainbow, 1000), frame_at(&rainbow, 2000));
+    }
+
+    #[test]
+    fn morse_sos() {
+        let sos = Pattern::Morse { color: RED, text: "SOS", unit_ms: 10 };
+        let expected: Vec<bool> = "1010100011101110111000101010000000"
+            .bytes()
+            .map(|b| b == b'1')
+            .collect();
+        assert_eq!(morse_len("SOS"), 34);
+        assert_eq!(on_off(sos, 10, 34), expected);
+        // and again from the top
+        assert_eq!(on_off(sos, 10, 68)[34..], expected[..]);
+    }
+
+    #[test]
+    fn morse_wakes_at_segment_ends() {
+        let sos = Pattern::Morse { color: RED, text: "SOS", unit_ms: 10 };
+        assert_eq!(next_change(&sos, 0), Some(10));
+        assert_eq!(next_change(&sos, 5), Some(5));
+        // the first O dash covers units 8 to 10
+        assert_eq!(next_change(&sos, 85), Some(25));
+        // the closing word gap
+        assert_eq!(next_change(&sos, 300), Some(40));
+    }
+
+    #[test]
+    fn morse_skips_unknown_characters() {
+        assert_eq!(morse_len("E"), 8);
+        assert_eq!(morse_len("E?"), 8);
+        // a space adds 4 units to the 3 after the letter
+        assert_eq!(morse_len("E E"), 16);
+    }
+}
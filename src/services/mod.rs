@@ -0,0 +1,6 @@
+pub mod battery_policy;
+pub mod deauth;
+pub mod led;
+pub mod power;
+pub mod rogue_ap;
+pub mod watchdog;
@@ -0,0 +1,54 @@
+use crate::screen::ScreenState;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PowerAction {
+    Stay,
+    LightSleep,
+    DeepSleep,
+}
+
+/// What to do with the screen in `screen` state, `idle_secs` after the
+/// last input. Nothing sleeps while the panels are lit or the radio has
+/// work, a `deep_after_secs` of 0 never sleeps deeply.
+pub fn decide(screen: ScreenState, radio_busy: bool, idle_secs: u64, deep_after_secs: u64) -> PowerAction {
+    if screen != ScreenState::Off || radio_busy {
+        PowerAction::Stay
+    } else if deep_after_secs != 0 && idle_secs >= deep_after_secs {
+        PowerAction::DeepSleep
+    } else {
+        PowerAction::LightSleep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEEP: u64 = 600;
+
+    #[test]
+    fn stays_awake_while_the_panels_are_lit() {
+        for screen in [ScreenState::On, ScreenState::Dimmed] {
+            assert_eq!(decide(screen, false, 0, DEEP), PowerAction::Stay);
+            assert_eq!(decide(screen, false, DEEP * 2, DEEP), PowerAction::Stay);
+        }
+    }
+
+    #[test]
+    fn stays_awake_while_the_radio_is_busy() {
+        assert_eq!(decide(ScreenState::Off, true, 0, DEEP), PowerAction::Stay);
+        assert_eq!(decide(ScreenState::Off, true, DEEP * 2, DEEP), PowerAction::Stay);
+    }
+
+    #[test]
+    fn light_sleeps_until_the_deep_sleep_time() {
+        assert_eq!(decide(ScreenState::Off, false, 0, DEEP), PowerAction::LightSleep);
+        assert_eq!(decide(ScreenState::Off, false, DEEP - 1, DEEP), PowerAction::LightSleep);
+        assert_eq!(decide(ScreenState::Off, false, DEEP, DEEP), PowerAction::DeepSleep);
+    }
+
+    #[test]
+    fn zero_never_sleeps_deeply() {
+        assert_eq!(decide(ScreenState::Off, false, u64::MAX, 0), PowerAction::LightSleep);
+    }
+}
@@ -0,0 +1,338 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Auth {
+    Open,
+    Wep,
+    Wpa,
+    Wpa2,
+    Wpa3,
+    Enterprise,
+    Other,
+}
+
+impl Auth {
+    pub fn parse(s: &str) -> Option<Self> {
+        let auth = match s.trim() {
+            "open" => Auth::Open,
+            "wep" => Auth::Wep,
+            "wpa" => Auth::Wpa,
+            "wpa2" => Auth::Wpa2,
+            "wpa3" => Auth::Wpa3,
+            "enterprise" => Auth::Enterprise,
+            "other" => Auth::Other,
+            _ => return None,
+        };
+        Some(auth)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Auth::Open => "open",
+            Auth::Wep => "wep",
+            Auth::Wpa => "wpa",
+            Auth::Wpa2 => "wpa2",
+            Auth::Wpa3 => "wpa3",
+            Auth::Enterprise => "enterprise",
+            Auth::Other => "other",
+        }
+    }
+}
+
+/// One trusted access point from the allowlist on the SD card.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AllowEntry {
+    pub ssid: String,
+    pub bssid: [u8; 6],
+    pub auth: Auth,
+}
+
+/// One access point as seen in a scan.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sighting {
+    pub ssid: String,
+    pub bssid: [u8; 6],
+    pub auth: Auth,
+    pub channel: u8,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Incident {
+    /// A watched SSID is broadcast by a BSSID that is not on the allowlist.
+    UnknownBssid { ssid: String, bssid: [u8; 6], channel: u8 },
+    /// A trusted BSSID advertises a different auth mode than expected.
+    AuthMismatch { ssid: String, bssid: [u8; 6], expected: Auth, seen: Auth },
+    /// A watched SSID is seen with more than one auth mode in the same scan.
+    MixedAuth { ssid: String, auths: (Auth, Auth) },
+    /// A watched SSID is seen on more than one channel in the same scan.
+    MixedChannel { ssid: String, channels: (u8, u8) },
+}
+
+impl Incident {
+    pub fn ssid(&self) -> &str {
+        match self {
+            Incident::UnknownBssid { ssid, .. }
+            | Incident::AuthMismatch { ssid, .. }
+            | Incident::MixedAuth { ssid, .. }
+            | Incident::MixedChannel { ssid, .. } => ssid,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Incident::UnknownBssid { ssid, bssid, channel } => format!(
+                "unknown-bssid ssid={} bssid={} ch={}",
+                ssid, format_bssid(bssid), channel
+            ),
+            Incident::AuthMismatch { ssid, bssid, expected, seen } => format!(
+                "auth-mismatch ssid={} bssid={} expected={} seen={}",
+                ssid, format_bssid(bssid), expected.as_str(), seen.as_str()
+            ),
+            Incident::MixedAuth { ssid, auths } => format!(
+                "mixed-auth ssid={} auth={}/{}",
+                ssid, auths.0.as_str(), auths.1.as_str()
+            ),
+            Incident::MixedChannel { ssid, channels } => format!(
+                "mixed-channel ssid={} ch={}/{}",
+                ssid, channels.0, channels.1
+            ),
+        }
+    }
+}
+
+/// Compare a scan against the allowlist. Only SSIDs present in the
+/// allowlist are checked, everything else is ignored.
+pub fn detect(allowlist: &[AllowEntry], scan: &[Sighting]) -> Vec<Incident> {
+    let mut incidents = Vec::new();
+
+    for seen in scan {
+        let mut watched = false;
+        let mut trusted = None;
+
+        for entry in allowlist.iter().filter(|e| e.ssid == seen.ssid) {
+            watched = true;
+            if entry.bssid == seen.bssid {
+                trusted = Some(entry);
+                break;
+            }
+        }
+
+        if !watched {
+            continue;
+        }
+
+        match trusted {
+            None => incidents.push(Incident::UnknownBssid {
+                ssid: seen.ssid.clone(),
+                bssid: seen.bssid,
+                channel: seen.channel,
+            }),
+            Some(entry) if entry.auth != seen.auth => incidents.push(Incident::AuthMismatch {
+                ssid: seen.ssid.clone(),
+                bssid: seen.bssid,
+                expected: entry.auth,
+                seen: seen.auth,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (i, first) in scan.iter().enumerate() {
+        if !allowlist.iter().any(|e| e.ssid == first.ssid) {
+            continue;
+        }
+        // report each SSID once, from its first sighting
+        if scan[..i].iter().any(|s| s.ssid == first.ssid) {
+            continue;
+        }
+
+        let others = scan[i + 1..].iter().filter(|s| s.ssid == first.ssid);
+
+        if let Some(other) = others.clone().find(|s| s.auth != first.auth) {
+            incidents.push(Incident::MixedAuth {
+                ssid: first.ssid.clone(),
+                auths: (first.auth, other.auth),
+            });
+        }
+        if let Some(other) = others.clone().find(|s| s.channel != first.channel) {
+            incidents.push(Incident::MixedChannel {
+                ssid: first.ssid.clone(),
+                channels: (first.channel, other.channel),
+            });
+        }
+    }
+
+    incidents
+}
+
+/// Parse the allowlist file. One `ssid,bssid,auth` entry per line,
+/// e.g. `HomeNet,aa:bb:cc:dd:ee:ff,wpa2`. Empty lines and lines
+/// starting with `#` are skipped, as are malformed entries.
+pub fn parse_allowlist(contents: &str) -> Vec<AllowEntry> {
+    let mut entries = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // SSIDs may contain commas, so split from the right
+        let mut fields = line.rsplitn(3, ',');
+        let (Some(auth), Some(bssid), Some(ssid)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let (Some(auth), Some(bssid)) = (Auth::parse(auth), parse_bssid(bssid)) else {
+            continue;
+        };
+
+        entries.push(AllowEntry {
+            ssid: String::from(ssid),
+            bssid,
+            auth,
+        });
+    }
+
+    entries
+}
+
+pub fn parse_bssid(s: &str) -> Option<[u8; 6]> {
+    let mut bssid = [0u8; 6];
+    let mut parts = s.trim().split(':');
+
+    for byte in bssid.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(bssid)
+}
+
+pub fn format_bssid(bssid: &[u8; 6]) -> String {
+    format!(
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        bssid[0], bssid[1], bssid[2], bssid[3], bssid[4], bssid[5]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOME: [u8; 6] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    const OTHER: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+    fn allow(ssid: &str, bssid: [u8; 6], auth: Auth) -> AllowEntry {
+        AllowEntry { ssid: String::from(ssid), bssid, auth }
+    }
+
+    fn seen(ssid: &str, bssid: [u8; 6], auth: Auth, channel: u8) -> Sighting {
+        Sighting { ssid: String::from(ssid), bssid, auth, channel }
+    }
+
+    #[test]
+    fn trusted_access_point_is_quiet() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2)];
+        let scan = [seen("HomeNet", HOME, Auth::Wpa2, 6), seen("Cafe", OTHER, Auth::Open, 1)];
+        assert!(detect(&allowlist, &scan).is_empty());
+    }
+
+    #[test]
+    fn unwatched_ssids_are_ignored() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2)];
+        let scan = [seen("Cafe", OTHER, Auth::Open, 1), seen("Cafe", HOME, Auth::Wpa2, 11)];
+        assert!(detect(&allowlist, &scan).is_empty());
+    }
+
+    #[test]
+    fn unknown_bssid_for_watched_ssid() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2)];
+        let scan = [seen("HomeNet", OTHER, Auth::Wpa2, 6)];
+        assert_eq!(
+            detect(&allowlist, &scan),
+            [Incident::UnknownBssid { ssid: String::from("HomeNet"), bssid: OTHER, channel: 6 }]
+        );
+    }
+
+    #[test]
+    fn auth_downgrade_of_trusted_bssid() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2)];
+        let scan = [seen("HomeNet", HOME, Auth::Open, 6)];
+        assert_eq!(
+            detect(&allowlist, &scan),
+            [Incident::AuthMismatch {
+                ssid: String::from("HomeNet"),
+                bssid: HOME,
+                expected: Auth::Wpa2,
+                seen: Auth::Open,
+            }]
+        );
+    }
+
+    #[test]
+    fn any_allowlisted_bssid_is_trusted() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2), allow("HomeNet", OTHER, Auth::Wpa2)];
+        let scan = [seen("HomeNet", HOME, Auth::Wpa2, 6), seen("HomeNet", OTHER, Auth::Wpa2, 6)];
+        assert!(detect(&allowlist, &scan).is_empty());
+    }
+
+    #[test]
+    fn mixed_auth_and_channel_reported_once_per_ssid() {
+        let allowlist = [allow("HomeNet", HOME, Auth::Wpa2), allow("HomeNet", OTHER, Auth::Wpa2)];
+        let scan = [
+            seen("HomeNet", HOME, Auth::Wpa2, 6),
+            seen("HomeNet", OTHER, Auth::Wpa2, 11),
+            seen("HomeNet", OTHER, Auth::Open, 11),
+        ];
+        let incidents = detect(&allowlist, &scan);
+
+        assert!(incidents.contains(&Incident::MixedAuth {
+            ssid: String::from("HomeNet"),
+            auths: (Auth::Wpa2, Auth::Open),
+        }));
+        assert!(incidents.contains(&Incident::MixedChannel {
+            ssid: String::from("HomeNet"),
+            channels: (6, 11),
+        }));
+        let mixed = incidents
+            .iter()
+            .filter(|i| matches!(i, Incident::MixedAuth { .. } | Incident::MixedChannel { .. }))
+            .count();
+        assert_eq!(mixed, 2);
+    }
+
+    #[test]
+    fn allowlist_skips_comments_and_blank_lines() {
+        let entries = parse_allowlist("# trusted\n\nHomeNet,aa:bb:cc:dd:ee:ff,wpa2\n  \n");
+        assert_eq!(entries, [allow("HomeNet", HOME, Auth::Wpa2)]);
+    }
+
+    #[test]
+    fn allowlist_ssid_may_contain_commas() {
+        let entries = parse_allowlist("Bob, Alice, and Eve,11:22:33:44:55:66,open");
+        assert_eq!(entries, [allow("Bob, Alice, and Eve", OTHER, Auth::Open)]);
+    }
+
+    #[test]
+    fn allowlist_drops_malformed_entries() {
+        let entries = parse_allowlist(
+            "NoAuth,aa:bb:cc:dd:ee:ff\n\
+             BadAuth,aa:bb:cc:dd:ee:ff,wpa9\n\
+             ShortBssid,aa:bb:cc:dd:ee,wpa2\n\
+             LongBssid,aa:bb:cc:dd:ee:ff:00,wpa2\n\
+             NotHex,aa:bb:cc:dd:ee:zz,wpa2\n\
+             HomeNet, aa:bb:cc:dd:ee:ff , wpa2 \n",
+        );
+        assert_eq!(entries, [allow("HomeNet", HOME, Auth::Wpa2)]);
+    }
+
+    #[test]
+    fn bssid_round_trip() {
+        assert_eq!(parse_bssid(&format_bssid(&HOME)), Some(HOME));
+        assert_eq!(format_bssid(&OTHER), "11:22:33:44:55:66");
+    }
+}
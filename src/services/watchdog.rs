@@ -0,0 +1,185 @@
+const TASKS: usize = 8;
+
+/// A task that has to check in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Task {
+    Button,
+    Menu,
+    Status,
+    Radio,
+    WifiScan,
+    Storage,
+    TopDisplay,
+    BottomDisplay,
+}
+
+impl Task {
+    pub const ALL: [Task; TASKS] = [
+        Task::Button,
+        Task::Menu,
+        Task::Status,
+        Task::Radio,
+        Task::WifiScan,
+        Task::Storage,
+        Task::TopDisplay,
+        Task::BottomDisplay,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::Button => "button_task",
+            Task::Menu => "menu_task",
+            Task::Status => "status_task",
+            Task::Radio => "radio_task",
+            Task::WifiScan => "wifi_scan_task",
+            Task::Storage => "storage_task",
+            Task::TopDisplay => "top display_task",
+            Task::BottomDisplay => "bottom display_task",
+        }
+    }
+
+    /// Longest time between two check-ins while the task is running.
+    pub const fn deadline_ms(&self) -> u64 {
+        match self {
+            Task::Button | Task::Status | Task::TopDisplay | Task::BottomDisplay => 3_000,
+            Task::Menu => 5_000,
+            // a factory reset deletes several files
+            Task::Radio | Task::Storage => 30_000,
+            // an active scan visits every channel
+            Task::WifiScan => 20_000,
+        }
+    }
+
+    /// The longest `deadline_ms` of any task.
+    pub const fn longest_deadline_ms() -> u64 {
+        let mut longest = 0;
+        let mut i = 0;
+        while i < TASKS {
+            let deadline = Task::ALL[i].deadline_ms();
+            if deadline > longest {
+                longest = deadline;
+            }
+            i += 1;
+        }
+        longest
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Beat {
+    /// Not started yet, not watched.
+    Unseen,
+    /// Last check-in, in ms since boot.
+    Running(u64),
+    /// Parked on an event that may take forever, not watched.
+    Waiting,
+}
+
+/// When each task last checked in. Times are passed in, so this can be
+/// exercised off the device.
+pub struct Heartbeats {
+    beats: [Beat; TASKS],
+}
+
+impl Heartbeats {
+    pub const fn new() -> Self {
+        Self { beats: [Beat::Unseen; TASKS] }
+    }
+
+    pub fn check_in(&mut self, task: Task, now_ms: u64) {
+        self.beats[task as usize] = Beat::Running(now_ms);
+    }
+
+    /// `task` is about to wait for an event with no deadline. It is
+    /// watched again from its next check-in.
+    pub fn wait(&mut self, task: Task) {
+        self.beats[task as usize] = Beat::Waiting;
+    }
+
+    /// Restart every running deadline, after a stretch in which no task
+    /// could have run.
+    pub fn excuse(&mut self, now_ms: u64) {
+        for beat in self.beats.iter_mut() {
+            if let Beat::Running(_) = beat {
+                *beat = Beat::Running(now_ms);
+            }
+        }
+    }
+
+    /// The task furthest past its deadline and by how many ms.
+    pub fn overdue(&self, now_ms: u64) -> Option<(Task, u64)> {
+        Task::ALL
+            .iter()
+            .filter_map(|&task| match self.beats[task as usize] {
+                Beat::Running(last) => {
+                    let late = now_ms.saturating_sub(last).saturating_sub(task.deadline_ms());
+                    (late > 0).then_some((task, late))
+                }
+                Beat::Unseen | Beat::Waiting => None,
+            })
+            .max_by_key(|&(_, late)| late)
+    }
+}
+
+impl Default for Heartbeats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unseen_tasks_are_not_watched() {
+        let beats = Heartbeats::new();
+        assert_eq!(beats.overdue(u64::MAX), None);
+    }
+
+    #[test]
+    fn overdue_once_the_deadline_has_passed() {
+        let mut beats = Heartbeats::new();
+        beats.check_in(Task::Button, 1_000);
+        assert_eq!(beats.overdue(4_000), None);
+        assert_eq!(beats.overdue(4_001), Some((Task::Button, 1)));
+
+        beats.check_in(Task::Button, 4_000);
+        assert_eq!(beats.overdue(4_001), None);
+    }
+
+    #[test]
+    fn waiting_tasks_are_not_watched_until_they_check_in() {
+        let mut beats = Heartbeats::new();
+        beats.check_in(Task::Radio, 0);
+        beats.wait(Task::Radio);
+        assert_eq!(beats.overdue(1_000_000), None);
+
+        beats.check_in(Task::Radio, 1_000_000);
+        assert_eq!(beats.overdue(1_030_500), Some((Task::Radio, 500)));
+    }
+
+    #[test]
+    fn reports_the_task_furthest_behind() {
+        let mut beats = Heartbeats::new();
+        beats.check_in(Task::Menu, 0);
+        beats.check_in(Task::Status, 0);
+        // Menu is 1 s late, Status 3 s
+        assert_eq!(beats.overdue(6_000), Some((Task::Status, 3_000)));
+    }
+
+    #[test]
+    fn excuse_restarts_running_deadlines_only() {
+        let mut beats = Heartbeats::new();
+        beats.check_in(Task::Status, 0);
+        beats.wait(Task::Storage);
+        beats.excuse(60_000);
+        assert_eq!(beats.overdue(63_000), None);
+        assert_eq!(beats.overdue(63_001), Some((Task::Status, 1)));
+    }
+
+    #[test]
+    fn longest_deadline() {
+        assert_eq!(Task::longest_deadline_ms(), 30_000);
+    }
+}
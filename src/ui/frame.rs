@@ -0,0 +1,543 @@
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const WIDTH: usize = 128;
+/// Tallest supported panel.
+pub const MAX_HEIGHT: usize = 64;
+const MAX_PAGES: usize = MAX_HEIGHT / 8;
+const MAX_FRAME_BYTES: usize = WIDTH * MAX_PAGES;
+
+/// Bus bytes for the column and page address commands of one region,
+/// each is address + control byte + 3 command bytes.
+const REGION_OVERHEAD: usize = 2 * 5;
+/// display-interface-i2c sends data in transfers of 16 bytes, each with
+/// its own address and control byte.
+const DATA_CHUNK: usize = 16;
+/// Clock of esp-hal's default I2C config, what the panels ran at before
+/// the board picked one.
+pub const DEFAULT_I2C_KHZ: u32 = 100;
+
+/// Anything a composed frame can be pushed to, the SSD1306 and SH1106
+/// panels on the device or a stand-in in the unit tests.
+#[allow(async_fn_in_trait, reason = "only used with concrete types inside this firmware")]
+pub trait FrameSink {
+    type Error;
+
+    async fn set_power(&mut self, on: bool) -> Result<(), Self::Error>;
+
+    /// Brightness in percent.
+    async fn set_level(&mut self, percent: u8) -> Result<(), Self::Error>;
+
+    /// Replace `region` with `data`, laid out page by page.
+    async fn write_region(&mut self, region: Region, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// 1bpp frame in SSD1306 GDDRAM layout: one byte per column per 8 pixel
+/// page, LSB at the top. `WIDTH` wide and a whole number of pages high.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Frame {
+    buf: [u8; MAX_FRAME_BYTES],
+    height: u8,
+}
+
+impl Frame {
+    /// `height` is rounded down to whole pages.
+    pub const fn new(height: usize) -> Self {
+        let height = if height > MAX_HEIGHT { MAX_HEIGHT } else { height / 8 * 8 };
+        Self {
+            buf: [0; MAX_FRAME_BYTES],
+            height: height as u8,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub fn pages(&self) -> usize {
+        self.height() / 8
+    }
+
+    pub fn clear_buffer(&mut self) {
+        self.buf.fill(0);
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x >= WIDTH || y >= self.height() {
+            return;
+        }
+        let byte = &mut self.buf[(y / 8) * WIDTH + x];
+        let bit = 1 << (y % 8);
+        if on {
+            *byte |= bit;
+        } else {
+            *byte &= !bit;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        x < WIDTH && y < self.height() && self.buf[(y / 8) * WIDTH + x] & (1 << (y % 8)) != 0
+    }
+
+    pub fn page(&self, page: usize) -> &[u8] {
+        &self.buf[page * WIDTH..(page + 1) * WIDTH]
+    }
+
+    /// Copy `part` in from page `first_page` down, as far as it fits.
+    pub fn blit(&mut self, part: &Frame, first_page: usize) {
+        let pages = part.pages().min(self.pages().saturating_sub(first_page));
+        let start = first_page * WIDTH;
+        self.buf[start..start + pages * WIDTH].copy_from_slice(&part.buf[..pages * WIDTH]);
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, self.height() as u32)
+    }
+}
+
+impl DrawTarget for Frame {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as usize, point.y as usize, color.is_on());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rectangle of the panel to rewrite, in whole pages and columns.
+/// `last_page` is inclusive, `end_col` is exclusive.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Region {
+    pub first_page: u8,
+    pub last_page: u8,
+    pub start_col: u8,
+    pub end_col: u8,
+}
+
+impl Region {
+    /// All of a panel `pages` pages high.
+    pub const fn full(pages: usize) -> Region {
+        Region {
+            first_page: 0,
+            last_page: pages as u8 - 1,
+            start_col: 0,
+            end_col: WIDTH as u8,
+        }
+    }
+
+    pub fn data_len(&self) -> usize {
+        (self.last_page - self.first_page + 1) as usize * (self.end_col - self.start_col) as usize
+    }
+
+    /// Bytes put on the I2C bus to send this region.
+    pub fn bus_bytes(&self) -> usize {
+        let data = self.data_len();
+        REGION_OVERHEAD + data + data.div_ceil(DATA_CHUNK) * 2
+    }
+}
+
+/// Bus bytes of a whole-panel flush, what every frame used to cost.
+pub fn full_flush_bus_bytes(pages: usize) -> usize {
+    Region::full(pages).bus_bytes()
+}
+
+/// Time `bus_bytes` take on an I2C bus clocked at `khz`, 9 clocks per
+/// byte with the acknowledge. Start and stop conditions are left out.
+pub fn bus_time_us(bus_bytes: usize, khz: u32) -> u32 {
+    (bus_bytes as u64 * 9 * 1000 / khz.max(1) as u64) as u32
+}
+
+/// Changed column span of every page, `None` for untouched pages and
+/// pages below the frame.
+fn dirty_spans(prev: &Frame, next: &Frame) -> [Option<(u8, u8)>; MAX_PAGES] {
+    let mut spans = [None; MAX_PAGES];
+
+    for (page, span) in spans.iter_mut().enumerate().take(next.pages()) {
+        let (a, b) = (prev.page(page), next.page(page));
+        let Some(start) = (0..WIDTH).find(|&x| a[x] != b[x]) else {
+            continue;
+        };
+        let end = (start..WIDTH).rev().find(|&x| a[x] != b[x]).unwrap_or(start) + 1;
+        *span = Some((start as u8, end as u8));
+    }
+
+    spans
+}
+
+/// Regions to send to turn `prev` into `next`. Uses one region per
+/// dirty page, or a single bounding region when that is cheaper on the
+/// bus. Empty when nothing changed.
+pub fn dirty_regions(prev: &Frame, next: &Frame) -> Vec<Region> {
+    let per_page: Vec<Region> = dirty_spans(prev, next)
+        .iter()
+        .enumerate()
+        .filter_map(|(page, span)| {
+            span.map(|(start_col, end_col)| Region {
+                first_page: page as u8,
+                last_page: page as u8,
+                start_col,
+                end_col,
+            })
+        })
+        .collect();
+
+    if per_page.len() < 2 {
+        return per_page;
+    }
+
+    let bounding = Region {
+        first_page: per_page.iter().map(|r| r.first_page).min().unwrap_or(0),
+        last_page: per_page.iter().map(|r| r.last_page).max().unwrap_or(0),
+        start_col: per_page.iter().map(|r| r.start_col).min().unwrap_or(0),
+        end_col: per_page.iter().map(|r| r.end_col).max().unwrap_or(0),
+    };
+
+    let per_page_cost: usize = per_page.iter().map(Region::bus_bytes).sum();
+    if bounding.bus_bytes() < per_page_cost {
+        vec![bounding]
+    } else {
+        per_page
+    }
+}
+
+/// Remembers what a panel shows so `flush` only sends what changed.
+pub struct Panel {
+    shown: Frame,
+    synced: bool,
+    frames: u32,
+    bus_bytes: u32,
+}
+
+impl Panel {
+    /// The first flush rewrites the whole panel, `height` rows high.
+    pub const fn new(height: usize) -> Self {
+        Self {
+            shown: Frame::new(height),
+            synced: false,
+            frames: 0,
+            bus_bytes: 0,
+        }
+    }
+
+    /// Send the regions of `frame` that differ from the panel contents
+    /// to `sink`. Returns the number of bytes put on the bus.
+    pub async fn flush<S: FrameSink>(&mut self, sink: &mut S, frame: &Frame) -> Result<usize, S::Error> {
+        let regions = if self.synced {
+            dirty_regions(&self.shown, frame)
+        } else {
+            vec![Region::full(frame.pages())]
+        };
+
+        let mut sent = 0;
+        let mut data = [0u8; MAX_FRAME_BYTES];
+
+        // if this fails half way the panel contents are unknown
+        self.synced = false;
+
+        for region in regions {
+            let cols = region.start_col as usize..region.end_col as usize;
+            let mut len = 0;
+            for page in region.first_page..=region.last_page {
+                let bytes = &frame.page(page as usize)[cols.clone()];
+                data[len..len + bytes.len()].copy_from_slice(bytes);
+                len += bytes.len();
+            }
+
+            sink.write_region(region, &data[..len]).await?;
+            sent += region.bus_bytes();
+        }
+
+        self.shown.clone_from(frame);
+        self.synced = true;
+        self.frames = self.frames.wrapping_add(1);
+        self.bus_bytes = self.bus_bytes.wrapping_add(sent as u32);
+        Ok(sent)
+    }
+
+    /// Average bus bytes per flushed frame since the last call.
+    pub fn take_average_bytes(&mut self) -> u32 {
+        let avg = self.bus_bytes.checked_div(self.frames).unwrap_or(0);
+        self.frames = 0;
+        self.bus_bytes = 0;
+        avg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+    use super::*;
+
+    /// Panel stand-in that applies regions to its own copy of the frame
+    /// and counts bus bytes like the I2C panels do.
+    struct Mirror {
+        shown: Frame,
+        regions: Vec<Region>,
+        bus_bytes: usize,
+        fail: bool,
+    }
+
+    impl Mirror {
+        fn new(height: usize) -> Self {
+            Self { shown: Frame::new(height), regions: Vec::new(), bus_bytes: 0, fail: false }
+        }
+    }
+
+    impl FrameSink for Mirror {
+        type Error = ();
+
+        async fn set_power(&mut self, _on: bool) -> Result<(), ()> {
+            Ok(())
+        }
+
+        async fn set_level(&mut self, _percent: u8) -> Result<(), ()> {
+            Ok(())
+        }
+
+        async fn write_region(&mut self, region: Region, data: &[u8]) -> Result<(), ()> {
+            if self.fail {
+                return Err(());
+            }
+            assert_eq!(data.len(), region.data_len());
+            let width = (region.end_col - region.start_col) as usize;
+            for (page, row) in (region.first_page..=region.last_page).zip(data.chunks(width)) {
+                let start = page as usize * WIDTH + region.start_col as usize;
+                self.shown.buf[start..start + width].copy_from_slice(row);
+            }
+            self.regions.push(region);
+            self.bus_bytes += region.bus_bytes();
+            Ok(())
+        }
+    }
+
+    fn fill(frame: &mut Frame, x: i32, y: i32, w: u32, h: u32, on: bool) {
+        let color = if on { BinaryColor::On } else { BinaryColor::Off };
+        Rectangle::new(Point::new(x, y), Size::new(w, h))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(frame)
+            .unwrap();
+    }
+
+    /// Flush `frame` and check the mirror caught up. Returns bus bytes.
+    fn flush(panel: &mut Panel, mirror: &mut Mirror, frame: &Frame) -> usize {
+        mirror.regions.clear();
+        let sent = block_on(panel.flush(mirror, frame)).unwrap();
+        assert!(mirror.shown == *frame);
+        sent
+    }
+
+    #[test]
+    fn height_is_whole_pages() {
+        assert_eq!(Frame::new(30).height(), 24);
+        assert_eq!(Frame::new(64).pages(), 8);
+        assert_eq!(Frame::new(200).height(), MAX_HEIGHT);
+    }
+
+    #[test]
+    fn pixels_land_in_gddram_layout() {
+        let mut frame = Frame::new(32);
+        frame.set_pixel(3, 9, true);
+        assert_eq!(frame.page(1)[3], 0b10);
+        assert!(frame.pixel(3, 9));
+        // off the frame is ignored
+        frame.set_pixel(WIDTH, 0, true);
+        frame.set_pixel(0, 32, true);
+        assert!(!frame.pixel(0, 32));
+    }
+
+    #[test]
+    fn blit_clips_to_the_target() {
+        let mut part = Frame::new(24);
+        fill(&mut part, 0, 0, 128, 24, true);
+        let mut panel = Frame::new(32);
+        panel.blit(&part, 2);
+        assert!(!panel.pixel(0, 15));
+        assert!(panel.pixel(0, 16));
+        assert!(panel.pixel(127, 31));
+    }
+
+    #[test]
+    fn full_flush_of_a_128x32_panel() {
+        // 512 data bytes in 32 chunks plus the two address commands
+        assert_eq!(full_flush_bus_bytes(4), 512 + 32 * 2 + 10);
+        assert_eq!(full_flush_bus_bytes(8), 1024 + 64 * 2 + 10);
+    }
+
+    #[test]
+    fn nothing_changed_sends_nothing() {
+        let frame = Frame::new(32);
+        assert!(dirty_regions(&frame, &frame.clone()).is_empty());
+    }
+
+    #[test]
+    fn one_pixel_is_one_column() {
+        let prev = Frame::new(32);
+        let mut next = prev.clone();
+        next.set_pixel(40, 20, true);
+        assert_eq!(
+            dirty_regions(&prev, &next),
+            [Region { first_page: 2, last_page: 2, start_col: 40, end_col: 41 }]
+        );
+    }
+
+    #[test]
+    fn stacked_changes_share_a_region() {
+        let prev = Frame::new(64);
+        let mut next = prev.clone();
+        fill(&mut next, 10, 0, 20, 24, true);
+        assert_eq!(
+            dirty_regions(&prev, &next),
+            [Region { first_page: 0, last_page: 2, start_col: 10, end_col: 30 }]
+        );
+    }
+
+    #[test]
+    fn far_apart_changes_stay_apart() {
+        let prev = Frame::new(64);
+        let mut next = prev.clone();
+        next.set_pixel(0, 0, true);
+        next.set_pixel(127, 63, true);
+        assert_eq!(
+            dirty_regions(&prev, &next),
+            [
+                Region { first_page: 0, last_page: 0, start_col: 0, end_col: 1 },
+                Region { first_page: 7, last_page: 7, start_col: 127, end_col: 128 },
+            ]
+        );
+    }
+
+    #[test]
+    fn first_flush_rewrites_the_panel() {
+        let mut panel = Panel::new(32);
+        let mut mirror = Mirror::new(32);
+        let frame = Frame::new(32);
+        assert_eq!(flush(&mut panel, &mut mirror, &frame), full_flush_bus_bytes(4));
+        assert_eq!(mirror.regions, [Region::full(4)]);
+        assert_eq!(flush(&mut panel, &mut mirror, &frame), 0);
+    }
+
+    #[test]
+    fn failed_flush_resyncs() {
+        let mut panel = Panel::new(32);
+        let mut mirror = Mirror::new(32);
+        let mut frame = Frame::new(32);
+        flush(&mut panel, &mut mirror, &frame);
+
+        frame.set_pixel(5, 5, true);
+        mirror.fail = true;
+        assert!(block_on(panel.flush(&mut mirror, &frame)).is_err());
+        mirror.fail = false;
+        assert_eq!(flush(&mut panel, &mut mirror, &frame), full_flush_bus_bytes(4));
+    }
+
+    #[test]
+    fn mirror_tracks_scattered_edits() {
+        let mut panel = Panel::new(64);
+        let mut mirror = Mirror::new(64);
+        let mut frame = Frame::new(64);
+        let mut seed: u32 = 1;
+        let mut next = move |max: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % max
+        };
+
+        for _ in 0..200 {
+            for _ in 0..next(4) {
+                let (x, y) = (next(128) as i32, next(64) as i32);
+                fill(&mut frame, x, y, next(40) + 1, next(20) + 1, next(2) == 0);
+            }
+            let sent = flush(&mut panel, &mut mirror, &frame);
+            assert!(sent <= full_flush_bus_bytes(8));
+            assert_eq!(sent, mirror.regions.iter().map(Region::bus_bytes).sum::<usize>());
+        }
+    }
+
+    /// Bus bytes per frame of a few things the UI does all the time, on
+    /// a 128x32 panel, against what a full flush costs.
+    #[test]
+    fn typical_updates_beat_a_full_flush() {
+        let full = full_flush_bus_bytes(4);
+        let mut panel = Panel::new(32);
+        let mut mirror = Mirror::new(32);
+        let mut frame = Frame::new(32);
+        fill(&mut frame, 0, 0, 128, 10, true);
+        flush(&mut panel, &mut mirror, &frame);
+
+        // menu cursor moves one line down
+        fill(&mut frame, 0, 0, 128, 10, false);
+        fill(&mut frame, 0, 10, 128, 10, true);
+        let cursor = flush(&mut panel, &mut mirror, &frame);
+
+        // the clock ticks a minute, two digits in the top bar
+        fill(&mut frame, 98, 22, 12, 8, true);
+        let clock = flush(&mut panel, &mut mirror, &frame);
+
+        // a marquee scrolls one line of text by a pixel
+        let mut marquee = 0;
+        for step in 0..6 {
+            fill(&mut frame, 0, 22, 128, 10, false);
+            for x in (step..128).step_by(6) {
+                fill(&mut frame, x, 23, 3, 7, true);
+            }
+            marquee += flush(&mut panel, &mut mirror, &frame);
+        }
+        let average = (full + cursor + clock + marquee) / 9;
+        let marquee = marquee / 6;
+
+        let pages = |first_page, last_page| {
+            Region { first_page, last_page, start_col: 0, end_col: WIDTH as u8 }.bus_bytes()
+        };
+        // a line of text straddles pages
+        assert_eq!(cursor, pages(0, 2));
+        assert!(clock * 10 < full, "clock {clock} of {full}");
+        assert!(marquee <= pages(2, 3), "marquee {marquee} of {full}");
+        assert_eq!(panel.take_average_bytes(), average as u32);
+        assert_eq!(panel.take_average_bytes(), 0);
+    }
+
+    #[test]
+    fn bus_time_scales_with_the_clock() {
+        assert_eq!(bus_time_us(100, 100), 9_000);
+        assert_eq!(bus_time_us(100, 400), 2_250);
+        assert_eq!(bus_time_us(100, 1_000), 900);
+        assert_eq!(bus_time_us(0, 400), 0);
+    }
+
+    /// Frame time of a menu cursor move on a 128x32 panel: a full flush
+    /// at the old default clock against the dirty pages at 400 kHz.
+    #[test]
+    fn cursor_move_before_and_after() {
+        let before = bus_time_us(full_flush_bus_bytes(4), DEFAULT_I2C_KHZ);
+
+        let mut panel = Panel::new(32);
+        let mut mirror = Mirror::new(32);
+        let mut frame = Frame::new(32);
+        fill(&mut frame, 0, 0, 128, 10, true);
+        flush(&mut panel, &mut mirror, &frame);
+        fill(&mut frame, 0, 0, 128, 10, false);
+        fill(&mut frame, 0, 10, 128, 10, true);
+        let after = bus_time_us(flush(&mut panel, &mut mirror, &frame), 400);
+
+        assert!(before > 50_000, "before {before} us");
+        assert!(after * 5 < before, "after {after} us, before {before} us");
+    }
+}
@@ -0,0 +1,47 @@
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    pixelcolor::BinaryColor,
+    prelude::*,
+};
+
+use crate::frame::Frame;
+
+// generated by build.rs from the PBM files in icons/
+include!(concat!(env!("OUT_DIR"), "/icons.rs"));
+
+/// Height of every status icon, they sit one pixel below the top of a
+/// 10 pixel text row.
+pub const ICON_HEIGHT: u32 = 8;
+
+pub fn battery(percent: u8, charging: bool) -> &'static ImageRaw<'static, BinaryColor> {
+    if charging {
+        return &BATTERY_CHARGING;
+    }
+    match percent {
+        0..=10 => &BATTERY_0,
+        11..=35 => &BATTERY_1,
+        36..=60 => &BATTERY_2,
+        61..=85 => &BATTERY_3,
+        _ => &BATTERY_4,
+    }
+}
+
+/// Signal bars for an RSSI, no bars without a network.
+pub fn wifi(rssi: Option<i8>) -> &'static ImageRaw<'static, BinaryColor> {
+    match rssi {
+        None => &WIFI_0,
+        Some(rssi) if rssi >= -55 => &WIFI_4,
+        Some(rssi) if rssi >= -65 => &WIFI_3,
+        Some(rssi) if rssi >= -75 => &WIFI_2,
+        Some(_) => &WIFI_1,
+    }
+}
+
+pub fn width(icon: &ImageRaw<'static, BinaryColor>) -> u32 {
+    icon.size().width
+}
+
+/// Draw `icon` with its top left corner at `x`, `y`.
+pub fn draw(frame: &mut Frame, icon: &ImageRaw<'static, BinaryColor>, x: i32, y: i32) {
+    Image::new(icon, Point::new(x, y)).draw(frame).ok();
+}
@@ -0,0 +1,145 @@
+/// Top bar strip on a shared panel, in whole pages so parts can be
+/// copied in page by page. Fits the widget row and one line of text.
+const SHARED_TOP_BAR_HEIGHT: usize = 24;
+/// A shared panel needs room for the menu title and a few lines below
+/// the strip.
+const SHARED_MIN_HEIGHT: usize = 64;
+
+/// How the top bar and the menu are put on the panels that came up.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// Each on a panel of its own, `top` and `bottom` rows high.
+    Split { top: u8, bottom: u8 },
+    /// One panel `height` rows high, the top bar is a strip above the
+    /// menu.
+    Shared { height: u8 },
+    /// One panel too short to share, the menu gets all of it.
+    MenuOnly { height: u8 },
+}
+
+/// What one panel shows.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Content {
+    TopBar,
+    Menu,
+    Both,
+}
+
+impl Layout {
+    /// Layout for the panel heights that answered at boot.
+    pub fn for_panels(top: Option<usize>, bottom: Option<usize>) -> Layout {
+        match (top, bottom) {
+            (Some(top), Some(bottom)) => Layout::Split { top: top as u8, bottom: bottom as u8 },
+            (Some(height), None) | (None, Some(height)) if height >= SHARED_MIN_HEIGHT => {
+                Layout::Shared { height: height as u8 }
+            }
+            (Some(height), None) | (None, Some(height)) => Layout::MenuOnly { height: height as u8 },
+            // nothing to show on, keep the classic sizes
+            (None, None) => Layout::Split { top: 32, bottom: 32 },
+        }
+    }
+
+    /// Rows of the top bar frame, 0 when it is not shown.
+    pub fn top_bar_height(&self) -> usize {
+        match *self {
+            Layout::Split { top, .. } => top as usize,
+            Layout::Shared { .. } => SHARED_TOP_BAR_HEIGHT,
+            Layout::MenuOnly { .. } => 0,
+        }
+    }
+
+    /// Rows of the menu frame.
+    pub fn menu_height(&self) -> usize {
+        match *self {
+            Layout::Split { bottom, .. } => bottom as usize,
+            Layout::Shared { height } => height as usize - SHARED_TOP_BAR_HEIGHT,
+            Layout::MenuOnly { height } => height as usize,
+        }
+    }
+
+    /// Panel row the menu starts at.
+    pub fn menu_y(&self) -> usize {
+        match self {
+            Layout::Shared { .. } => SHARED_TOP_BAR_HEIGHT,
+            Layout::Split { .. } | Layout::MenuOnly { .. } => 0,
+        }
+    }
+
+    /// What the top panel shows, or the bottom one if `top` is false.
+    /// Only meaningful for a panel that came up.
+    pub fn content(&self, top: bool) -> Content {
+        match self {
+            Layout::Split { .. } if top => Content::TopBar,
+            Layout::Split { .. } | Layout::MenuOnly { .. } => Content::Menu,
+            Layout::Shared { .. } => Content::Both,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::string::String;
+
+    /// One letter per page of the top panel, or the bottom one if `top`
+    /// is false. `T` is the top bar, `M` the menu.
+    fn pages(layout: Layout, top: bool, height: usize) -> String {
+        (0..height / 8)
+            .map(|page| {
+                let y = page * 8;
+                match layout.content(top) {
+                    Content::TopBar => 'T',
+                    Content::Menu => 'M',
+                    Content::Both if y < layout.menu_y() => 'T',
+                    Content::Both => 'M',
+                }
+            })
+            .collect()
+    }
+
+    /// The layout for the panels that answered, drawn panel by panel.
+    fn snapshot(top: Option<usize>, bottom: Option<usize>) -> String {
+        let layout = Layout::for_panels(top, bottom);
+        let mut out = String::new();
+        for (name, is_top, height) in [("top", true, top), ("bottom", false, bottom)] {
+            if let Some(height) = height {
+                out.push_str(&alloc::format!("{name} {height}: {}\n", pages(layout, is_top, height)));
+            }
+        }
+        out.push_str(&alloc::format!("bar {} menu {}", layout.top_bar_height(), layout.menu_height()));
+        out
+    }
+
+    #[test]
+    fn two_short_panels_split() {
+        assert_eq!(snapshot(Some(32), Some(32)), "top 32: TTTT\nbottom 32: MMMM\nbar 32 menu 32");
+    }
+
+    #[test]
+    fn short_top_and_tall_bottom_split() {
+        assert_eq!(snapshot(Some(32), Some(64)), "top 32: TTTT\nbottom 64: MMMMMMMM\nbar 32 menu 64");
+    }
+
+    #[test]
+    fn one_tall_panel_is_shared() {
+        assert_eq!(snapshot(Some(64), None), "top 64: TTTMMMMM\nbar 24 menu 40");
+        assert_eq!(snapshot(None, Some(64)), "bottom 64: TTTMMMMM\nbar 24 menu 40");
+    }
+
+    #[test]
+    fn one_short_panel_shows_the_menu_only() {
+        assert_eq!(snapshot(Some(32), None), "top 32: MMMM\nbar 0 menu 32");
+        assert_eq!(snapshot(None, Some(32)), "bottom 32: MMMM\nbar 0 menu 32");
+    }
+
+    #[test]
+    fn no_panel_keeps_the_classic_sizes() {
+        assert_eq!(snapshot(None, None), "bar 32 menu 32");
+    }
+
+    #[test]
+    fn shared_strip_is_whole_pages() {
+        assert!(SHARED_TOP_BAR_HEIGHT.is_multiple_of(8));
+    }
+}
@@ -0,0 +1,793 @@
+use core::future::Future;
+
+use embassy_futures::select::{select3, Either3};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::button::ButtonEvent;
+use crate::rogue_ap::Auth;
+use crate::text::{self, FontSize};
+
+const CRUMB_SEPARATOR: &str = " > ";
+const CRUMB_ELIDED: &str = "..";
+
+pub enum MenuAction {
+    Enter(&'static Menu),
+    Trigger(MenuCommand),
+    WifiAp(&'static WifiApInfo),
+    Toggle(Toggle),
+    Choice(Choice),
+    Slider(Slider),
+    /// Read-only text, redrawn while the menu is shown.
+    Value(fn() -> String),
+}
+
+/// Checkbox, Select flips it.
+#[derive(Copy, Clone)]
+pub struct Toggle {
+    pub get: fn() -> bool,
+    pub set: fn(bool),
+}
+
+/// One of `options`, Select moves on to the next.
+#[derive(Copy, Clone)]
+pub struct Choice {
+    pub options: &'static [&'static str],
+    pub get: fn() -> usize,
+    pub set: fn(usize),
+}
+
+impl Choice {
+    pub fn next(&self) {
+        (self.set)(((self.get)() + 1) % self.options.len());
+    }
+}
+
+/// Number in `min..=max`. Select starts editing, Up and Down then step
+/// it until Select or Back.
+#[derive(Copy, Clone)]
+pub struct Slider {
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+    pub unit: &'static str,
+    pub get: fn() -> i32,
+    pub set: fn(i32),
+}
+
+impl Slider {
+    pub fn adjust(&self, steps: i32) {
+        (self.set)(((self.get)() + steps * self.step).clamp(self.min, self.max));
+    }
+}
+
+impl MenuAction {
+    /// What is shown on the right of the label, if anything.
+    pub fn value_text(&self, editing: bool) -> Option<String> {
+        match self {
+            MenuAction::Toggle(toggle) => {
+                Some(String::from(if (toggle.get)() { "[x]" } else { "[ ]" }))
+            }
+            MenuAction::Choice(choice) => {
+                choice.options.get((choice.get)()).map(|option| String::from(*option))
+            }
+            MenuAction::Slider(slider) if editing => {
+                Some(format!("<{}{}>", (slider.get)(), slider.unit))
+            }
+            MenuAction::Slider(slider) => Some(format!("{}{}", (slider.get)(), slider.unit)),
+            MenuAction::Value(value) => Some(value()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum MenuCommand {
+    BleScan,
+    WifiScan,
+    WifiClearSelected,
+    ClockHourUp,
+    ClockMinuteUp,
+    ResetTopBar,
+    Reboot,
+    PowerOff,
+    FactoryReset,
+    /// Append the crash reported at boot to the crash log.
+    SaveCrash,
+    DeleteFile(&'static str),
+    EnterDynamic(&'static Menu),
+}
+
+impl MenuCommand {
+    /// Title and question of the dialog that has to be confirmed before
+    /// the command runs, `None` for commands that run right away.
+    pub fn confirmation(&self) -> Option<(&'static str, String)> {
+        match self {
+            MenuCommand::Reboot => Some(("Reboot", String::from("Restart the device?"))),
+            MenuCommand::PowerOff => Some(("Power Off", String::from("Press Select to wake."))),
+            MenuCommand::FactoryReset => {
+                Some(("Factory Reset", String::from("Erase settings and logs?")))
+            }
+            MenuCommand::DeleteFile(file) => Some(("Delete", format!("Delete {}?", file))),
+            _ => None,
+        }
+    }
+}
+
+pub struct MenuItem {
+    pub label: &'static str,
+    pub action: MenuAction,
+}
+
+pub struct Menu {
+    pub title: &'static str,
+    pub items: &'static [MenuItem],
+}
+
+/// A menu on the stack and where its cursor was left.
+#[derive(Copy, Clone)]
+pub struct MenuLevel {
+    pub menu: &'static Menu,
+    pub selected: usize,
+    pub scroll: usize,
+}
+
+/// The stack grows as deep as the menus go. `selected` and `scroll`
+/// belong to the top level, the levels below keep their own.
+pub struct MenuState {
+    pub stack: Vec<MenuLevel>,
+    pub selected: usize,
+    pub scroll: usize,
+    /// Up and Down go to the selected slider instead of moving the cursor.
+    pub editing: bool,
+}
+
+impl MenuState {
+    pub fn new(root: &'static Menu) -> Self {
+        Self {
+            stack: vec![MenuLevel { menu: root, selected: 0, scroll: 0 }],
+            selected: 0,
+            scroll: 0,
+            editing: false,
+        }
+    }
+
+    pub fn current(&self) -> &'static Menu {
+        self.stack[self.stack.len() - 1].menu
+    }
+
+    pub fn enter(&mut self, menu: &'static Menu) {
+        if let Some(level) = self.stack.last_mut() {
+            level.selected = self.selected;
+            level.scroll = self.scroll;
+        }
+        self.stack.push(MenuLevel { menu, selected: 0, scroll: 0 });
+        self.selected = 0;
+        self.scroll = 0;
+        self.editing = false;
+    }
+
+    /// Return to the parent menu with the cursor where it was left.
+    pub fn back(&mut self) {
+        self.editing = false;
+        if self.stack.len() > 1 {
+            self.stack.pop();
+            if let Some(level) = self.stack.last() {
+                self.selected = level.selected;
+                self.scroll = level.scroll;
+            }
+        }
+    }
+
+    /// Act on a press while no dialog is open: move the cursor, enter and
+    /// leave menus, change toggles, choices and sliders. Returns what is
+    /// left for the menu task to do. The cursor stays within the
+    /// `visible` rows.
+    pub fn press(&mut self, button: ButtonEvent, visible: usize) -> Option<Effect> {
+        let len = self.current().items.len();
+        let mut effect = None;
+
+        match button {
+            ButtonEvent::Up if self.editing => self.adjust_slider(1),
+            ButtonEvent::Down if self.editing => self.adjust_slider(-1),
+            ButtonEvent::Select | ButtonEvent::Back if self.editing => self.editing = false,
+            ButtonEvent::Up if len == 0 => self.selected = 0,
+            ButtonEvent::Up if self.selected > 0 => self.selected -= 1,
+            ButtonEvent::Up => self.selected = len - 1,
+            ButtonEvent::Down if self.selected + 1 < len => self.selected += 1,
+            ButtonEvent::Down => self.selected = 0,
+            // nothing to select in an empty menu, a scan that found nothing
+            ButtonEvent::Select => match self.current().items.get(self.selected).map(|item| &item.action) {
+                None | Some(MenuAction::Value(_)) => {}
+                Some(MenuAction::WifiAp(ap)) => effect = Some(Effect::SelectAp(ap)),
+                Some(MenuAction::Enter(sub)) | Some(MenuAction::Trigger(MenuCommand::EnterDynamic(sub))) => {
+                    self.enter(sub)
+                }
+                Some(MenuAction::Toggle(toggle)) => (toggle.set)(!(toggle.get)()),
+                Some(MenuAction::Choice(choice)) => choice.next(),
+                Some(MenuAction::Slider(_)) => self.editing = true,
+                Some(MenuAction::Trigger(cmd)) => effect = Some(Effect::Command(*cmd)),
+            },
+            ButtonEvent::Back => self.back(),
+        }
+
+        self.normalize(visible);
+        effect
+    }
+
+    fn adjust_slider(&self, steps: i32) {
+        if let Some(MenuItem { action: MenuAction::Slider(slider), .. }) =
+            self.current().items.get(self.selected)
+        {
+            slider.adjust(steps);
+        }
+    }
+
+    /// Keep the cursor on an item and scroll it into the `visible` rows.
+    pub fn normalize(&mut self, visible: usize) {
+        let len = self.current().items.len();
+
+        if len == 0 {
+            self.selected = 0;
+            self.scroll = 0;
+            return;
+        }
+
+        if self.selected >= len {
+            self.selected = len - 1;
+        }
+
+        if self.scroll > self.selected {
+            self.scroll = self.selected;
+        }
+
+        if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+    }
+}
+
+/// What a press leaves for the menu task to do.
+#[derive(Copy, Clone)]
+pub enum Effect {
+    /// Make this the selected access point.
+    SelectAp(&'static WifiApInfo),
+    /// Start a scan, ask to confirm the command or hand it to its task.
+    Command(MenuCommand),
+}
+
+/// Asked of the menu task by other tasks. `D` is the dialog message of
+/// the firmware.
+#[derive(Clone)]
+pub enum MenuRequest<D> {
+    /// Enter a menu built at runtime, e.g. the scan results.
+    PushMenu(&'static Menu),
+    /// Open, update or close a dialog.
+    Dialog(D),
+}
+
+/// What woke the menu task.
+pub enum MenuEvent<D> {
+    Request(MenuRequest<D>),
+    Button(ButtonEvent),
+    /// Redraw timer for scrolling labels, live values and dialogs.
+    Tick,
+}
+
+/// Wait for whichever comes first of a request from another task, a
+/// button and the redraw `tick`. A request that is already waiting wins,
+/// so pushed menus and dialogs show without a press.
+pub async fn next_event<D>(
+    requests: impl Future<Output = MenuRequest<D>>,
+    buttons: impl Future<Output = ButtonEvent>,
+    tick: impl Future<Output = ()>,
+) -> MenuEvent<D> {
+    match select3(requests, buttons, tick).await {
+        Either3::First(request) => MenuEvent::Request(request),
+        Either3::Second(button) => MenuEvent::Button(button),
+        Either3::Third(()) => MenuEvent::Tick,
+    }
+}
+
+#[derive(PartialEq)]
+pub struct WifiApInfo {
+    pub ssid: &'static str,
+    pub bssid: [u8; 6],
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth: Auth,
+}
+
+/// `Main > WiFi > Networks` in at most `max_width` pixels. Ancestors are
+/// dropped from the left first, then the current title is shortened.
+pub fn breadcrumb(titles: &[&str], max_width: u32, size: FontSize) -> String {
+    let Some(current) = titles.last() else {
+        return String::new();
+    };
+
+    for skip in 0..titles.len() {
+        let mut crumb = String::new();
+        if skip > 0 {
+            crumb.push_str(CRUMB_ELIDED);
+            crumb.push_str(CRUMB_SEPARATOR);
+        }
+        crumb.push_str(&titles[skip..].join(CRUMB_SEPARATOR));
+
+        if text::width(&crumb, size) <= max_width {
+            return crumb;
+        }
+    }
+
+    text::ellipsize(current, max_width, size)
+}
+
+/// Start and length of the scrollbar thumb on a track `track` pixels
+/// high, `None` when the whole list fits.
+pub fn scrollbar_thumb(total: usize, visible: usize, scroll: usize, track: u32) -> Option<(u32, u32)> {
+    if total <= visible {
+        return None;
+    }
+
+    let len = (track * visible as u32 / total as u32).max(2);
+    let max_scroll = (total - visible) as u32;
+    let start = (track - len) * (scroll as u32).min(max_scroll) / max_scroll;
+    Some((start, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::{pending, ready};
+    use core::ptr;
+    use core::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+
+    use embassy_futures::block_on;
+    use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+
+    use super::*;
+    use ButtonEvent::{Back, Down, Select, Up};
+
+    fn nothing() -> String {
+        String::new()
+    }
+
+    const fn item(label: &'static str, action: MenuAction) -> MenuItem {
+        MenuItem { label, action }
+    }
+
+    static LEAF: Menu = Menu {
+        title: "Leaf",
+        items: &[item("One", MenuAction::Value(nothing)), item("Two", MenuAction::Value(nothing))],
+    };
+    static BRANCH: Menu = Menu {
+        title: "Branch",
+        items: &[
+            item("A", MenuAction::Value(nothing)),
+            item("B", MenuAction::Value(nothing)),
+            item("Leaf", MenuAction::Enter(&LEAF)),
+        ],
+    };
+    static ROOT: Menu = Menu {
+        title: "Root",
+        items: &[
+            item("Branch", MenuAction::Enter(&BRANCH)),
+            item("Leaf", MenuAction::Enter(&LEAF)),
+            item("Scan", MenuAction::Trigger(MenuCommand::WifiScan)),
+        ],
+    };
+
+    fn titles(state: &MenuState) -> Vec<&'static str> {
+        state.stack.iter().map(|level| level.menu.title).collect()
+    }
+
+    #[test]
+    fn starts_at_the_root() {
+        let state = MenuState::new(&ROOT);
+        assert!(ptr::eq(state.current(), &ROOT));
+        assert_eq!(titles(&state), ["Root"]);
+        assert_eq!((state.selected, state.scroll, state.editing), (0, 0, false));
+    }
+
+    #[test]
+    fn enter_starts_at_the_top() {
+        let mut state = MenuState::new(&ROOT);
+        state.selected = 1;
+        state.scroll = 1;
+        state.editing = true;
+        state.enter(&LEAF);
+        assert!(ptr::eq(state.current(), &LEAF));
+        assert_eq!(titles(&state), ["Root", "Leaf"]);
+        assert_eq!((state.selected, state.scroll, state.editing), (0, 0, false));
+    }
+
+    #[test]
+    fn back_restores_each_cursor() {
+        let mut state = MenuState::new(&ROOT);
+        state.selected = 2;
+        state.scroll = 1;
+        state.enter(&BRANCH);
+        state.selected = 2;
+        state.enter(&LEAF);
+        state.selected = 1;
+        assert_eq!(titles(&state), ["Root", "Branch", "Leaf"]);
+
+        state.back();
+        assert!(ptr::eq(state.current(), &BRANCH));
+        assert_eq!((state.selected, state.scroll), (2, 0));
+
+        state.back();
+        assert!(ptr::eq(state.current(), &ROOT));
+        assert_eq!((state.selected, state.scroll), (2, 1));
+    }
+
+    #[test]
+    fn back_stops_at_the_root() {
+        let mut state = MenuState::new(&ROOT);
+        state.selected = 1;
+        state.editing = true;
+        state.back();
+        state.back();
+        assert_eq!(titles(&state), ["Root"]);
+        assert_eq!(state.selected, 1);
+        assert!(!state.editing);
+    }
+
+    #[test]
+    fn same_menu_twice_keeps_separate_cursors() {
+        let mut state = MenuState::new(&ROOT);
+        state.enter(&LEAF);
+        state.selected = 1;
+        state.enter(&LEAF);
+        assert_eq!(state.selected, 0);
+        state.back();
+        assert_eq!(state.selected, 1);
+        assert_eq!(titles(&state), ["Root", "Leaf"]);
+    }
+
+    /// Every item leads one level further down.
+    static DEEP: Menu = Menu {
+        title: "Deep",
+        items: &[
+            item("A", MenuAction::Enter(&DEEP)),
+            item("B", MenuAction::Enter(&DEEP)),
+            item("C", MenuAction::Enter(&DEEP)),
+        ],
+    };
+
+    #[test]
+    fn deep_stacks_unwind_level_by_level() {
+        const LEVELS: usize = 7;
+        let mut state = MenuState::new(&DEEP);
+        // leave a different cursor on every level on the way down
+        for level in 0..LEVELS {
+            for _ in 0..level % 3 {
+                state.press(Down, 4);
+            }
+            assert!(state.press(Select, 4).is_none());
+        }
+        assert_eq!(state.stack.len(), LEVELS + 1);
+
+        let titles = titles(&state);
+        let crumb = breadcrumb(&titles, 128, FontSize::Small);
+        assert!(crumb.starts_with(".. > "), "{crumb}");
+        assert!(crumb.ends_with(" > Deep"), "{crumb}");
+        assert!(text::width(&crumb, FontSize::Small) <= 128);
+
+        for level in (0..LEVELS).rev() {
+            state.press(Back, 4);
+            assert_eq!(state.stack.len(), level + 1);
+            assert_eq!(state.selected, level % 3);
+        }
+        state.press(Back, 4);
+        assert_eq!(state.stack.len(), 1);
+    }
+
+    static EMPTY: Menu = Menu { title: "Empty", items: &[] };
+    static HOME: WifiApInfo = WifiApInfo {
+        ssid: "HomeNet",
+        bssid: [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff],
+        rssi: -50,
+        channel: 6,
+        auth: Auth::Wpa2,
+    };
+    static NETWORKS: Menu = Menu {
+        title: "Networks",
+        items: &[item("HomeNet", MenuAction::WifiAp(&HOME))],
+    };
+    static LONG: Menu = Menu {
+        title: "Long",
+        items: &[
+            item("0", MenuAction::Value(nothing)),
+            item("1", MenuAction::Value(nothing)),
+            item("2", MenuAction::Value(nothing)),
+            item("3", MenuAction::Value(nothing)),
+            item("4", MenuAction::Trigger(MenuCommand::EnterDynamic(&EMPTY))),
+            item("5", MenuAction::Trigger(MenuCommand::EnterDynamic(&NETWORKS))),
+        ],
+    };
+
+    /// Feed `buttons` to `state` the way menu_task does, with two rows
+    /// on screen. Returns the effects that came out.
+    fn script(state: &mut MenuState, buttons: &[ButtonEvent]) -> Vec<Option<Effect>> {
+        buttons.iter().map(|&button| state.press(button, 2)).collect()
+    }
+
+    fn cursor(state: &MenuState) -> (&'static str, usize, usize) {
+        (state.current().title, state.selected, state.scroll)
+    }
+
+    #[test]
+    fn cursor_wraps_around() {
+        let mut state = MenuState::new(&ROOT);
+        script(&mut state, &[Up]);
+        assert_eq!(cursor(&state), ("Root", 2, 1));
+        script(&mut state, &[Down]);
+        assert_eq!(cursor(&state), ("Root", 0, 0));
+        script(&mut state, &[Down, Down, Down, Down]);
+        assert_eq!(cursor(&state), ("Root", 1, 0));
+    }
+
+    #[test]
+    fn scrolling_follows_the_cursor() {
+        let mut state = MenuState::new(&LONG);
+        script(&mut state, &[Down, Down, Down]);
+        assert_eq!(cursor(&state), ("Long", 3, 2));
+        script(&mut state, &[Up, Up]);
+        assert_eq!(cursor(&state), ("Long", 1, 1));
+        script(&mut state, &[Up, Up]);
+        assert_eq!(cursor(&state), ("Long", 5, 4));
+    }
+
+    #[test]
+    fn select_enters_and_back_returns() {
+        let mut state = MenuState::new(&ROOT);
+        let effects = script(&mut state, &[Select, Down, Down, Select, Down]);
+        assert!(effects.iter().all(Option::is_none));
+        assert_eq!(cursor(&state), ("Leaf", 1, 0));
+        assert_eq!(titles(&state), ["Root", "Branch", "Leaf"]);
+
+        script(&mut state, &[Back]);
+        assert_eq!(cursor(&state), ("Branch", 2, 1));
+        script(&mut state, &[Back, Back]);
+        assert_eq!(cursor(&state), ("Root", 0, 0));
+    }
+
+    #[test]
+    fn empty_menu_ignores_presses() {
+        let mut state = MenuState::new(&LONG);
+        script(&mut state, &[Up, Up]);
+        let effects = script(&mut state, &[Select, Select, Up, Down, Select]);
+        assert!(effects.iter().all(Option::is_none));
+        assert_eq!(cursor(&state), ("Empty", 0, 0));
+        script(&mut state, &[Back]);
+        assert_eq!(cursor(&state), ("Long", 4, 4));
+    }
+
+    #[test]
+    fn select_hands_out_commands_and_access_points() {
+        let mut state = MenuState::new(&ROOT);
+        script(&mut state, &[Up]);
+        assert!(matches!(
+            script(&mut state, &[Select])[..],
+            [Some(Effect::Command(MenuCommand::WifiScan))]
+        ));
+
+        let mut state = MenuState::new(&LONG);
+        let effects = script(&mut state, &[Up, Select, Select]);
+        assert_eq!(cursor(&state), ("Networks", 0, 0));
+        assert!(matches!(effects[2], Some(Effect::SelectAp(ap)) if ptr::eq(ap, &HOME)));
+    }
+
+    static WIFI: AtomicBool = AtomicBool::new(false);
+    static THEME: AtomicUsize = AtomicUsize::new(0);
+    static LEVEL: AtomicI32 = AtomicI32::new(50);
+    static CONTROLS: Menu = Menu {
+        title: "Controls",
+        items: &[
+            item(
+                "WiFi",
+                MenuAction::Toggle(Toggle {
+                    get: || WIFI.load(Ordering::Relaxed),
+                    set: |on| WIFI.store(on, Ordering::Relaxed),
+                }),
+            ),
+            item(
+                "Theme",
+                MenuAction::Choice(Choice {
+                    options: &["dark", "light", "auto"],
+                    get: || THEME.load(Ordering::Relaxed),
+                    set: |i| THEME.store(i, Ordering::Relaxed),
+                }),
+            ),
+            item(
+                "Level",
+                MenuAction::Slider(Slider {
+                    min: 0,
+                    max: 60,
+                    step: 5,
+                    unit: "%",
+                    get: || LEVEL.load(Ordering::Relaxed),
+                    set: |v| LEVEL.store(v, Ordering::Relaxed),
+                }),
+            ),
+        ],
+    };
+
+    #[test]
+    fn controls_change_in_place() {
+        let mut state = MenuState::new(&CONTROLS);
+        script(&mut state, &[Select]);
+        assert!(WIFI.load(Ordering::Relaxed));
+        assert_eq!(CONTROLS.items[0].action.value_text(false).as_deref(), Some("[x]"));
+        script(&mut state, &[Select]);
+        assert!(!WIFI.load(Ordering::Relaxed));
+
+        script(&mut state, &[Down, Select, Select, Select]);
+        assert_eq!(THEME.load(Ordering::Relaxed), 0);
+        script(&mut state, &[Select]);
+        assert_eq!(CONTROLS.items[1].action.value_text(false).as_deref(), Some("light"));
+        assert_eq!(cursor(&state), ("Controls", 1, 0));
+    }
+
+    #[test]
+    fn slider_takes_up_and_down_while_editing() {
+        let mut state = MenuState::new(&CONTROLS);
+        script(&mut state, &[Up, Select]);
+        assert!(state.editing);
+        assert_eq!(CONTROLS.items[2].action.value_text(true).as_deref(), Some("<50%>"));
+
+        script(&mut state, &[Up, Up, Up]);
+        assert_eq!(LEVEL.load(Ordering::Relaxed), 60);
+        script(&mut state, &[Down]);
+        assert_eq!(LEVEL.load(Ordering::Relaxed), 55);
+        assert_eq!(cursor(&state), ("Controls", 2, 1));
+
+        // Back ends editing and stays in the menu
+        script(&mut state, &[Back]);
+        assert!(!state.editing);
+        assert_eq!(cursor(&state), ("Controls", 2, 1));
+        script(&mut state, &[Up]);
+        assert_eq!(LEVEL.load(Ordering::Relaxed), 55);
+        assert_eq!(cursor(&state), ("Controls", 1, 1));
+    }
+
+    /// Dialog messages as the firmware sends them, reduced to what the
+    /// menu task does with them.
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum Popup {
+        Open(&'static str),
+        Close,
+    }
+
+    /// Buttons that nobody presses.
+    fn untouched() -> Channel<NoopRawMutex, ButtonEvent, 4> {
+        Channel::new()
+    }
+
+    /// One turn of menu_task without the drawing. Returns false for
+    /// anything but a request.
+    fn handle(state: &mut MenuState, popup: &mut Option<&'static str>, event: MenuEvent<Popup>) -> bool {
+        match event {
+            MenuEvent::Request(MenuRequest::PushMenu(menu)) => state.enter(menu),
+            MenuEvent::Request(MenuRequest::Dialog(Popup::Open(title))) => *popup = Some(title),
+            MenuEvent::Request(MenuRequest::Dialog(Popup::Close)) => *popup = None,
+            MenuEvent::Button(_) | MenuEvent::Tick => return false,
+        }
+        true
+    }
+
+    #[test]
+    fn requests_arrive_without_a_press() {
+        let requests: Channel<NoopRawMutex, MenuRequest<Popup>, 4> = Channel::new();
+        let buttons = untouched();
+        let mut state = MenuState::new(&ROOT);
+        let mut popup = None;
+
+        block_on(async {
+            // the scan task opens its progress dialog, pushes the results
+            // and closes the dialog, all while the buttons stay untouched
+            requests.send(MenuRequest::Dialog(Popup::Open("WiFi Scan"))).await;
+            let event = next_event(requests.receive(), buttons.receive(), pending()).await;
+            assert!(handle(&mut state, &mut popup, event));
+            assert_eq!(popup, Some("WiFi Scan"));
+
+            requests.send(MenuRequest::PushMenu(&LEAF)).await;
+            requests.send(MenuRequest::Dialog(Popup::Close)).await;
+            for _ in 0..2 {
+                let event = next_event(requests.receive(), buttons.receive(), pending()).await;
+                assert!(handle(&mut state, &mut popup, event));
+            }
+        });
+
+        assert!(ptr::eq(state.current(), &LEAF));
+        assert_eq!(popup, None);
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn waiting_requests_come_before_buttons() {
+        let requests: Channel<NoopRawMutex, MenuRequest<Popup>, 4> = Channel::new();
+        let buttons = untouched();
+        let mut state = MenuState::new(&ROOT);
+        let mut popup = None;
+
+        buttons.try_send(Down).ok();
+        requests.try_send(MenuRequest::PushMenu(&BRANCH)).ok();
+        block_on(async {
+            let event = next_event(requests.receive(), buttons.receive(), pending()).await;
+            assert!(handle(&mut state, &mut popup, event));
+            let event = next_event(requests.receive(), buttons.receive(), pending()).await;
+            assert!(matches!(event, MenuEvent::Button(Down)));
+        });
+        assert!(ptr::eq(state.current(), &BRANCH));
+    }
+
+    #[test]
+    fn tick_when_nothing_is_waiting() {
+        let requests: Channel<NoopRawMutex, MenuRequest<Popup>, 4> = Channel::new();
+        let buttons = untouched();
+        let event = block_on(next_event(requests.receive(), buttons.receive(), ready(())));
+        assert!(matches!(event, MenuEvent::Tick));
+    }
+
+    const PATH: [&str; 3] = ["Main Menu", "Settings", "Top Bar"];
+
+    fn crumb(titles: &[&str], max_width: u32) -> String {
+        breadcrumb(titles, max_width, FontSize::Small)
+    }
+
+    #[test]
+    fn breadcrumb_shows_the_whole_path_when_it_fits() {
+        assert_eq!(crumb(&PATH, 150), "Main Menu > Settings > Top Bar");
+        assert_eq!(crumb(&PATH[..1], 150), "Main Menu");
+        assert_eq!(crumb(&[], 150), "");
+    }
+
+    #[test]
+    fn breadcrumb_drops_ancestors_from_the_left() {
+        assert_eq!(crumb(&PATH, 149), ".. > Settings > Top Bar");
+        assert_eq!(crumb(&PATH, 115), ".. > Settings > Top Bar");
+        assert_eq!(crumb(&PATH, 114), ".. > Top Bar");
+        assert_eq!(crumb(&PATH, 60), ".. > Top Bar");
+    }
+
+    #[test]
+    fn breadcrumb_shortens_the_current_title_last() {
+        assert_eq!(crumb(&PATH, 59), "Top Bar");
+        assert_eq!(crumb(&PATH, 30), "Top...");
+        assert_eq!(crumb(&["WiFi Networks"], 40), "WiFi ...");
+    }
+
+    #[test]
+    fn no_scrollbar_when_everything_fits() {
+        assert_eq!(scrollbar_thumb(0, 3, 0, 24), None);
+        assert_eq!(scrollbar_thumb(3, 3, 0, 24), None);
+    }
+
+    #[test]
+    fn thumb_runs_from_top_to_bottom() {
+        assert_eq!(scrollbar_thumb(10, 3, 0, 24), Some((0, 7)));
+        assert_eq!(scrollbar_thumb(10, 3, 3, 24), Some((7, 7)));
+        assert_eq!(scrollbar_thumb(10, 3, 7, 24), Some((17, 7)));
+        // past the end sticks to the bottom
+        assert_eq!(scrollbar_thumb(10, 3, 9, 24), Some((17, 7)));
+    }
+
+    #[test]
+    fn thumb_stays_visible_on_long_lists() {
+        assert_eq!(scrollbar_thumb(200, 3, 0, 24), Some((0, 2)));
+        assert_eq!(scrollbar_thumb(200, 3, 197, 24), Some((22, 2)));
+    }
+
+    #[test]
+    fn thumb_never_leaves_the_track() {
+        for total in 4..40 {
+            for scroll in 0..total {
+                let (start, len) = scrollbar_thumb(total, 3, scroll, 56).unwrap();
+                assert!(start + len <= 56, "{total} items at {scroll}");
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod frame;
+pub mod icons;
+pub mod layout;
+pub mod menu;
+pub mod screen;
+pub mod text;
+pub mod widget;
@@ -0,0 +1,53 @@
+/// How long before switching off the panels are dimmed.
+const DIM_LEAD_SECS: u64 = 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ScreenState {
+    On,
+    Dimmed,
+    Off,
+}
+
+/// Screen state after `idle_secs` without input. A timeout of 0 keeps
+/// the panels on. Dimming starts `DIM_LEAD_SECS` before switching off,
+/// or half way for short timeouts.
+pub fn state_for(idle_secs: u64, timeout_secs: u16) -> ScreenState {
+    let timeout = timeout_secs as u64;
+    if timeout == 0 {
+        return ScreenState::On;
+    }
+
+    let dim_at = timeout - DIM_LEAD_SECS.min(timeout / 2);
+    if idle_secs >= timeout {
+        ScreenState::Off
+    } else if idle_secs >= dim_at {
+        ScreenState::Dimmed
+    } else {
+        ScreenState::On
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dims_ahead_of_the_timeout() {
+        assert_eq!(state_for(0, 60), ScreenState::On);
+        assert_eq!(state_for(49, 60), ScreenState::On);
+        assert_eq!(state_for(50, 60), ScreenState::Dimmed);
+        assert_eq!(state_for(60, 60), ScreenState::Off);
+    }
+
+    #[test]
+    fn short_timeouts_dim_half_way() {
+        assert_eq!(state_for(4, 10), ScreenState::On);
+        assert_eq!(state_for(5, 10), ScreenState::Dimmed);
+        assert_eq!(state_for(10, 10), ScreenState::Off);
+    }
+
+    #[test]
+    fn zero_timeout_stays_on() {
+        assert_eq!(state_for(u64::MAX, 0), ScreenState::On);
+    }
+}
@@ -0,0 +1,196 @@
+use embedded_graphics::{
+    mono_font::{iso_8859_1, MonoFont, MonoTextStyle, MonoTextStyleBuilder},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::frame::Frame;
+
+const ELLIPSIS: &str = "...";
+/// Drawn for anything the fonts have no glyph for.
+const FALLBACK: char = '?';
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FontSize {
+    Small,
+    Normal,
+    Large,
+}
+
+impl FontSize {
+    /// The Latin-1 variants, so accented SSIDs still come out readable.
+    pub fn font(&self) -> &'static MonoFont<'static> {
+        match self {
+            FontSize::Small => &iso_8859_1::FONT_5X7,
+            FontSize::Normal => &iso_8859_1::FONT_6X10,
+            FontSize::Large => &iso_8859_1::FONT_10X20,
+        }
+    }
+
+    /// Horizontal distance from one character to the next.
+    pub fn advance(&self) -> u32 {
+        let font = self.font();
+        font.character_size.width + font.character_spacing
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.font().character_size.height
+    }
+
+    pub fn style(&self, color: BinaryColor) -> MonoTextStyle<'static, BinaryColor> {
+        MonoTextStyleBuilder::new()
+            .font(self.font())
+            .text_color(color)
+            .build()
+    }
+}
+
+/// The character actually drawn for `c`.
+pub fn glyph(c: char) -> char {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c,
+        _ => FALLBACK,
+    }
+}
+
+/// `text` with every character the fonts can not draw replaced.
+pub fn sanitize(text: &str) -> String {
+    text.chars().map(glyph).collect()
+}
+
+/// Width in pixels, one glyph per character however many bytes it takes.
+pub fn width(text: &str, size: FontSize) -> u32 {
+    text.chars().count() as u32 * size.advance()
+}
+
+/// `text` cut down to `max_width` pixels, ending in an ellipsis if
+/// anything had to go.
+pub fn ellipsize(text: &str, max_width: u32, size: FontSize) -> String {
+    if width(text, size) <= max_width {
+        return sanitize(text);
+    }
+
+    let room = max_width.saturating_sub(width(ELLIPSIS, size)) / size.advance();
+    let mut out: String = text.chars().take(room as usize).map(glyph).collect();
+    // the ellipsis itself may not fit on a very narrow field
+    if width(ELLIPSIS, size) <= max_width {
+        out.push_str(ELLIPSIS);
+    }
+    out
+}
+
+/// Break `text` into lines of at most `max_width` pixels, at spaces
+/// where possible and mid-word for words longer than a line.
+pub fn wrap(text: &str, max_width: u32, size: FontSize) -> Vec<String> {
+    let per_line = (max_width / size.advance()).max(1) as usize;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().map(glyph).collect();
+
+        if line_len > 0 && line_len + 1 + word.len() <= per_line {
+            line.push(' ');
+            line.extend(word.iter());
+            line_len += 1 + word.len();
+            continue;
+        }
+
+        if line_len > 0 {
+            lines.push(core::mem::take(&mut line));
+        }
+
+        while word.len() > per_line {
+            let rest = word.split_off(per_line);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        line_len = word.len();
+        line.extend(word);
+    }
+
+    if line_len > 0 {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draw `text` with its top left corner at `x`, `y`.
+pub fn draw(frame: &mut Frame, text: &str, x: i32, y: i32, style: MonoTextStyle<'_, BinaryColor>) {
+    Text::with_baseline(text, Point::new(x, y), style, Baseline::Top)
+        .draw(frame)
+        .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_counts_characters_not_bytes() {
+        assert_eq!(width("", FontSize::Normal), 0);
+        assert_eq!(width("abc", FontSize::Normal), 18);
+        assert_eq!(width("Café", FontSize::Normal), 24);
+        assert_eq!(width("Café", FontSize::Small), 20);
+        assert_eq!(width("Café", FontSize::Large), 40);
+    }
+
+    #[test]
+    fn undrawable_characters_become_question_marks() {
+        assert_eq!(sanitize("Ça va"), "Ça va");
+        assert_eq!(sanitize("net→😀"), "net??");
+        assert_eq!(sanitize("tab\there"), "tab?here");
+    }
+
+    #[test]
+    fn short_text_is_left_alone() {
+        assert_eq!(ellipsize("HomeNet", 42, FontSize::Normal), "HomeNet");
+        assert_eq!(ellipsize("", 0, FontSize::Normal), "");
+    }
+
+    #[test]
+    fn long_text_ends_in_an_ellipsis() {
+        // 10 characters of room, 3 of them for the dots
+        assert_eq!(ellipsize("CoffeeShop-Guest", 60, FontSize::Normal), "CoffeeS...");
+        assert_eq!(ellipsize("CoffeeShop-Guest", 65, FontSize::Normal), "CoffeeS...");
+        assert_eq!(ellipsize("Crème brûlée", 54, FontSize::Normal), "Crème ...");
+        assert_eq!(ellipsize("net😀😀😀😀", 36, FontSize::Normal), "net...");
+    }
+
+    #[test]
+    fn narrow_fields_drop_the_ellipsis() {
+        assert_eq!(ellipsize("CoffeeShop", 18, FontSize::Normal), "...");
+        assert_eq!(ellipsize("CoffeeShop", 12, FontSize::Normal), "");
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces() {
+        assert_eq!(
+            wrap("Rogue AP seen on channel 6", 60, FontSize::Normal),
+            ["Rogue AP", "seen on", "channel 6"]
+        );
+        assert_eq!(wrap("  spaced   out  ", 60, FontSize::Normal), ["spaced out"]);
+        assert!(wrap("", 60, FontSize::Normal).is_empty());
+    }
+
+    #[test]
+    fn wrap_splits_long_words() {
+        assert_eq!(
+            wrap("aa:bb:cc:dd:ee:ff found", 30, FontSize::Normal),
+            ["aa:bb", ":cc:d", "d:ee:", "ff", "found"]
+        );
+        // always at least one character per line
+        assert_eq!(wrap("abc", 1, FontSize::Normal), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn wrap_counts_characters_not_bytes() {
+        assert_eq!(wrap("né né né", 30, FontSize::Normal), ["né né", "né"]);
+        assert_eq!(wrap("a😀b", 60, FontSize::Normal), ["a?b"]);
+    }
+}
@@ -0,0 +1,550 @@
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::frame::{Frame, WIDTH};
+use crate::icons::{self, ICON_HEIGHT};
+use crate::text::{self, FontSize};
+
+/// Space left between two widgets sharing a slot.
+const GAP: u32 = 4;
+const ROW_HEIGHT: u32 = 10;
+/// Space between an icon and the text next to it.
+const ICON_GAP: u32 = 2;
+
+/// Something that can be placed on the top bar.
+pub trait Widget {
+    /// Width in pixels the widget wants, 0 hides it.
+    fn preferred_width(&self) -> u32;
+
+    /// Draw into `area`, which is never wider than `preferred_width`.
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, tick: u32, style: MonoTextStyle<'_, BinaryColor>);
+}
+
+/// Widget that is a single line of text.
+pub trait TextWidget {
+    fn label(&self) -> String;
+}
+
+impl<T: TextWidget> Widget for T {
+    fn preferred_width(&self) -> u32 {
+        text::width(&self.label(), FontSize::Normal)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        text::draw(frame, &self.label(), area.top_left.x, area.top_left.y, style);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Slot {
+    Left,
+    Center,
+    Right,
+}
+
+impl Slot {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Slot::Left => "left",
+            Slot::Center => "center",
+            Slot::Right => "right",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WidgetKind {
+    Battery,
+    Clock,
+    Wifi,
+    Bluetooth,
+    Sd,
+    Notifications,
+    Heap,
+}
+
+impl WidgetKind {
+    pub const COUNT: usize = 7;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WidgetKind::Battery => "battery",
+            WidgetKind::Clock => "clock",
+            WidgetKind::Wifi => "wifi",
+            WidgetKind::Bluetooth => "bt",
+            WidgetKind::Sd => "sd",
+            WidgetKind::Notifications => "notify",
+            WidgetKind::Heap => "heap",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "battery" => Some(WidgetKind::Battery),
+            "clock" => Some(WidgetKind::Clock),
+            "wifi" => Some(WidgetKind::Wifi),
+            "bt" => Some(WidgetKind::Bluetooth),
+            "sd" => Some(WidgetKind::Sd),
+            "notify" => Some(WidgetKind::Notifications),
+            "heap" => Some(WidgetKind::Heap),
+            _ => None,
+        }
+    }
+}
+
+/// Which widgets are on the top bar, in which slot and in what order.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BarLayout {
+    entries: [Option<(WidgetKind, Slot)>; WidgetKind::COUNT],
+}
+
+impl BarLayout {
+    /// Battery on the left, clock on the right, like the old fixed bar.
+    pub const fn new() -> Self {
+        let mut entries = [None; WidgetKind::COUNT];
+        entries[0] = Some((WidgetKind::Battery, Slot::Left));
+        entries[1] = Some((WidgetKind::Clock, Slot::Right));
+        Self { entries }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (WidgetKind, Slot)> + '_ {
+        self.entries.iter().flatten().copied()
+    }
+
+    pub fn slot_of(&self, kind: WidgetKind) -> Option<Slot> {
+        self.iter().find(|(k, _)| *k == kind).map(|(_, slot)| slot)
+    }
+
+    /// Take `kind` off the bar.
+    pub fn remove(&mut self, kind: WidgetKind) {
+        let kept: Vec<_> = self.iter().filter(|(k, _)| *k != kind).collect();
+        self.entries = [None; WidgetKind::COUNT];
+        for (entry, item) in self.entries.iter_mut().zip(kept) {
+            *entry = Some(item);
+        }
+    }
+
+    /// Put `kind` at the end of `slot`, moving it if it was already shown.
+    pub fn place(&mut self, kind: WidgetKind, slot: Slot) {
+        self.remove(kind);
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.is_none()) {
+            *entry = Some((kind, slot));
+        }
+    }
+
+    /// Parse `battery:l,clock:r`. Unknown widgets are skipped.
+    pub fn parse(value: &str) -> Self {
+        let mut layout = Self { entries: [None; WidgetKind::COUNT] };
+
+        for item in value.split(',') {
+            let Some((name, slot)) = item.trim().split_once(':') else {
+                continue;
+            };
+            let slot = match slot {
+                "l" => Slot::Left,
+                "c" => Slot::Center,
+                "r" => Slot::Right,
+                _ => continue,
+            };
+            if let Some(kind) = WidgetKind::parse(name) {
+                layout.place(kind, slot);
+            }
+        }
+
+        layout
+    }
+
+    pub fn serialize(&self) -> String {
+        let items: Vec<String> = self
+            .iter()
+            .map(|(kind, slot)| format!("{}:{}", kind.name(), &slot.as_str()[..1]))
+            .collect();
+        items.join(",")
+    }
+}
+
+impl Default for BarLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Place widgets of the given slot and width on a row `width` pixels
+/// wide. Returns `(x, width)` for each one in order, `None` for hidden
+/// widgets and those that do not fit. Left is filled first, then right,
+/// center takes what is left in between.
+pub fn layout_row(items: &[(Slot, u32)], width: u32) -> Vec<Option<(u32, u32)>> {
+    let mut placed = alloc::vec![None; items.len()];
+
+    let mut left_end = 0;
+    for (i, &(slot, w)) in items.iter().enumerate() {
+        if slot != Slot::Left || w == 0 {
+            continue;
+        }
+        let x = if left_end == 0 { 0 } else { left_end + GAP };
+        if x + w > width {
+            continue;
+        }
+        placed[i] = Some((x, w));
+        left_end = x + w;
+    }
+
+    // the last right widget sits against the edge
+    let mut right_start = width;
+    for (i, &(slot, w)) in items.iter().enumerate().rev() {
+        if slot != Slot::Right || w == 0 {
+            continue;
+        }
+        let gap = if right_start == width { 0 } else { GAP };
+        let min_x = if left_end == 0 { 0 } else { left_end + GAP };
+        let Some(x) = right_start.checked_sub(w + gap) else {
+            continue;
+        };
+        if x < min_x {
+            continue;
+        }
+        placed[i] = Some((x, w));
+        right_start = x;
+    }
+
+    // drop trailing center widgets until the rest fits between the sides
+    let free_start = if left_end == 0 { 0 } else { left_end + GAP };
+    let free_end = if right_start == width { width } else { right_start.saturating_sub(GAP) };
+    let mut center: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, (slot, w))| *slot == Slot::Center && *w > 0)
+        .map(|(i, _)| i)
+        .collect();
+
+    let total = |center: &[usize]| -> u32 {
+        let widths: u32 = center.iter().map(|&i| items[i].1).sum();
+        widths + GAP * (center.len() as u32).saturating_sub(1)
+    };
+    while !center.is_empty() && total(&center) > free_end.saturating_sub(free_start) {
+        center.pop();
+    }
+
+    if !center.is_empty() {
+        let total = total(&center);
+        // centered on the whole bar when possible, else in the free space
+        let mut x = ((width - total) / 2).clamp(free_start, free_end - total);
+        for i in center {
+            placed[i] = Some((x, items[i].1));
+            x += items[i].1 + GAP;
+        }
+    }
+
+    placed
+}
+
+/// What the status widgets show, gathered once per frame.
+pub struct BarStatus {
+    pub battery_percent: u8,
+    pub charging: bool,
+    pub time_hhmm: (u8, u8),
+    /// RSSI of the selected access point.
+    pub wifi_rssi: Option<i8>,
+    pub bluetooth: bool,
+    pub sd_ok: bool,
+    pub notifications: u8,
+    pub free_heap: usize,
+}
+
+/// Top of an icon centered vertically in the text row of `area`.
+fn icon_y(area: &Rectangle) -> i32 {
+    area.top_left.y + (ROW_HEIGHT.saturating_sub(ICON_HEIGHT) / 2) as i32
+}
+
+pub struct BatteryWidget {
+    pub percent: u8,
+    pub charging: bool,
+}
+
+impl BatteryWidget {
+    fn label(&self) -> String {
+        format!("{}%", self.percent)
+    }
+}
+
+impl Widget for BatteryWidget {
+    fn preferred_width(&self) -> u32 {
+        let icon = icons::battery(self.percent, self.charging);
+        icons::width(icon) + ICON_GAP + text::width(&self.label(), FontSize::Normal)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        let icon = icons::battery(self.percent, self.charging);
+        icons::draw(frame, icon, area.top_left.x, icon_y(&area));
+        let x = area.top_left.x + (icons::width(icon) + ICON_GAP) as i32;
+        text::draw(frame, &self.label(), x, area.top_left.y, style);
+    }
+}
+
+pub struct ClockWidget {
+    pub hhmm: (u8, u8),
+}
+
+impl TextWidget for ClockWidget {
+    fn label(&self) -> String {
+        format!("{:02}:{:02}", self.hhmm.0, self.hhmm.1)
+    }
+}
+
+pub struct WifiWidget {
+    pub rssi: Option<i8>,
+}
+
+impl Widget for WifiWidget {
+    fn preferred_width(&self) -> u32 {
+        icons::width(icons::wifi(self.rssi))
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, _style: MonoTextStyle<'_, BinaryColor>) {
+        icons::draw(frame, icons::wifi(self.rssi), area.top_left.x, icon_y(&area));
+    }
+}
+
+/// Only shown while Bluetooth is on.
+pub struct BluetoothWidget {
+    pub enabled: bool,
+}
+
+impl Widget for BluetoothWidget {
+    fn preferred_width(&self) -> u32 {
+        if self.enabled { icons::width(&icons::BLUETOOTH) } else { 0 }
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, _style: MonoTextStyle<'_, BinaryColor>) {
+        icons::draw(frame, &icons::BLUETOOTH, area.top_left.x, icon_y(&area));
+    }
+}
+
+/// Blinks while the card can not be used.
+pub struct SdWidget {
+    pub ok: bool,
+}
+
+impl Widget for SdWidget {
+    fn preferred_width(&self) -> u32 {
+        icons::width(&icons::SD_CARD)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, tick: u32, _style: MonoTextStyle<'_, BinaryColor>) {
+        if self.ok || (tick / 5).is_multiple_of(2) {
+            icons::draw(frame, &icons::SD_CARD, area.top_left.x, icon_y(&area));
+        }
+    }
+}
+
+/// Only shown while there are unseen notifications.
+pub struct NotificationWidget {
+    pub count: u8,
+}
+
+impl Widget for NotificationWidget {
+    fn preferred_width(&self) -> u32 {
+        match self.count {
+            0 => 0,
+            n => icons::width(&icons::ALARM) + ICON_GAP + text::width(&format!("{}", n), FontSize::Normal),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rectangle, _tick: u32, style: MonoTextStyle<'_, BinaryColor>) {
+        icons::draw(frame, &icons::ALARM, area.top_left.x, icon_y(&area));
+        let x = area.top_left.x + (icons::width(&icons::ALARM) + ICON_GAP) as i32;
+        text::draw(frame, &format!("{}", self.count), x, area.top_left.y, style);
+    }
+}
+
+pub struct HeapWidget {
+    pub free: usize,
+}
+
+impl TextWidget for HeapWidget {
+    fn label(&self) -> String {
+        format!("{}K", self.free / 1024)
+    }
+}
+
+impl BarStatus {
+    pub fn widget(&self, kind: WidgetKind) -> Box<dyn Widget> {
+        match kind {
+            WidgetKind::Battery => Box::new(BatteryWidget {
+                percent: self.battery_percent,
+                charging: self.charging,
+            }),
+            WidgetKind::Clock => Box::new(ClockWidget { hhmm: self.time_hhmm }),
+            WidgetKind::Wifi => Box::new(WifiWidget { rssi: self.wifi_rssi }),
+            WidgetKind::Bluetooth => Box::new(BluetoothWidget { enabled: self.bluetooth }),
+            WidgetKind::Sd => Box::new(SdWidget { ok: self.sd_ok }),
+            WidgetKind::Notifications => Box::new(NotificationWidget { count: self.notifications }),
+            WidgetKind::Heap => Box::new(HeapWidget { free: self.free_heap }),
+        }
+    }
+}
+
+/// Lay out and draw the widgets of `layout` on the first row.
+pub fn draw_bar(
+    frame: &mut Frame,
+    layout: &BarLayout,
+    status: &BarStatus,
+    tick: u32,
+    style: MonoTextStyle<'_, BinaryColor>,
+) {
+    let mut widgets: Vec<(Slot, Box<dyn Widget>)> = layout
+        .iter()
+        .map(|(kind, slot)| (slot, status.widget(kind)))
+        .collect();
+    let sizes: Vec<(Slot, u32)> = widgets
+        .iter()
+        .map(|(slot, widget)| (*slot, widget.preferred_width()))
+        .collect();
+
+    for ((_, widget), place) in widgets.iter_mut().zip(layout_row(&sizes, WIDTH as u32)) {
+        if let Some((x, w)) = place {
+            let area = Rectangle::new(Point::new(x as i32, 0), Size::new(w, ROW_HEIGHT));
+            widget.draw(frame, area, tick, style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Slot::{Center as C, Left as L, Right as R};
+
+    /// The row as text, each placed widget drawn with its letter (`a` for
+    /// the first item) and free space as dots.
+    fn snapshot(items: &[(Slot, u32)], width: u32) -> String {
+        let mut row = alloc::vec!['.'; width as usize];
+        for (i, place) in layout_row(items, width).into_iter().enumerate() {
+            if let Some((x, w)) = place {
+                row[x as usize..(x + w) as usize].fill((b'a' + i as u8) as char);
+            }
+        }
+        row.into_iter().collect()
+    }
+
+    #[test]
+    fn sides_and_center() {
+        assert_eq!(
+            snapshot(&[(L, 6), (C, 4), (R, 6)], 40),
+            "aaaaaa............bbbb............cccccc",
+        );
+    }
+
+    #[test]
+    fn slots_keep_their_order() {
+        assert_eq!(
+            snapshot(&[(L, 6), (L, 5), (R, 6), (R, 3)], 40),
+            "aaaaaa....bbbbb............cccccc....ddd",
+        );
+    }
+
+    #[test]
+    fn overflow_drops_what_does_not_fit() {
+        assert_eq!(
+            snapshot(&[(L, 20), (L, 10), (L, 8)], 40),
+            "aaaaaaaaaaaaaaaaaaaa....bbbbbbbbbb......",
+        );
+        assert_eq!(
+            snapshot(&[(R, 20), (R, 10), (R, 8)], 40),
+            "..................bbbbbbbbbb....cccccccc",
+        );
+        assert_eq!(snapshot(&[(L, 41)], 40), "........................................");
+    }
+
+    #[test]
+    fn left_wins_over_right() {
+        assert_eq!(
+            snapshot(&[(L, 18), (R, 18), (C, 6)], 40),
+            "aaaaaaaaaaaaaaaaaa....bbbbbbbbbbbbbbbbbb",
+        );
+        assert_eq!(
+            snapshot(&[(L, 20), (R, 18)], 40),
+            "aaaaaaaaaaaaaaaaaaaa....................",
+        );
+    }
+
+    #[test]
+    fn hidden_widgets_take_no_room() {
+        assert_eq!(
+            snapshot(&[(L, 6), (L, 0), (C, 0), (R, 0), (R, 6)], 40),
+            "aaaaaa............................eeeeee",
+        );
+    }
+
+    #[test]
+    fn center_moves_aside_for_crowded_sides() {
+        assert_eq!(
+            snapshot(&[(L, 20), (C, 6), (R, 4)], 40),
+            "aaaaaaaaaaaaaaaaaaaa....bbbbbb......cccc",
+        );
+        assert_eq!(
+            snapshot(&[(L, 4), (C, 6), (R, 20)], 40),
+            "aaaa......bbbbbb....cccccccccccccccccccc",
+        );
+    }
+
+    #[test]
+    fn trailing_center_widgets_go_first() {
+        assert_eq!(
+            snapshot(&[(L, 12), (C, 8), (C, 8), (C, 8), (R, 4)], 40),
+            "aaaaaaaaaaaa....bbbbbbbb............eeee",
+        );
+        assert_eq!(
+            snapshot(&[(C, 10), (C, 10)], 40),
+            "........aaaaaaaaaa....bbbbbbbbbb........",
+        );
+    }
+
+    #[test]
+    fn default_bar() {
+        let status = BarStatus {
+            battery_percent: 80,
+            charging: false,
+            time_hhmm: (9, 5),
+            wifi_rssi: None,
+            bluetooth: false,
+            sd_ok: true,
+            notifications: 0,
+            free_heap: 0,
+        };
+        let sizes: Vec<(Slot, u32)> = BarLayout::new()
+            .iter()
+            .map(|(kind, slot)| (slot, status.widget(kind).preferred_width()))
+            .collect();
+        let clock = text::width("09:05", FontSize::Normal);
+        let placed = layout_row(&sizes, WIDTH as u32);
+        assert_eq!(placed[0].map(|(x, _)| x), Some(0));
+        assert_eq!(placed[1], Some((WIDTH as u32 - clock, clock)));
+    }
+
+    #[test]
+    fn layout_setting_round_trips() {
+        let layout = BarLayout::parse("wifi:l, battery:l,bogus:c,clock:r,sd:x,notify:c");
+        assert_eq!(layout.serialize(), "wifi:l,battery:l,clock:r,notify:c");
+        assert_eq!(BarLayout::parse(&layout.serialize()), layout);
+        assert_eq!(BarLayout::parse(&BarLayout::new().serialize()), BarLayout::new());
+    }
+
+    #[test]
+    fn placing_moves_a_widget() {
+        let mut layout = BarLayout::new();
+        layout.place(WidgetKind::Battery, Slot::Right);
+        assert_eq!(layout.serialize(), "clock:r,battery:r");
+        layout.remove(WidgetKind::Clock);
+        assert_eq!(layout.slot_of(WidgetKind::Clock), None);
+        assert_eq!(layout.slot_of(WidgetKind::Battery), Some(Slot::Right));
+    }
+}